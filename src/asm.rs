@@ -0,0 +1,781 @@
+//! An assembler for a subset of Octo, the most widely used CHIP-8
+//! assembly language.
+//!
+//! Supported are all instructions of CHIP-8, SUPER-CHIP and XO-CHIP,
+//! labels (`: name`), `:const`, `:alias`, `:macro`, `:call`, `:byte`,
+//! `:org`, raw data bytes, `if ... then`, `if ... begin ... else ...
+//! end` and `loop ... while ... again`. Calculated expressions, string
+//! and sprite directives and the comparison pseudo-ops are not.
+//!
+//! ```text
+//! :alias x v1
+//! :const speed 2
+//!
+//! : main
+//!     loop
+//!         x += speed
+//!         if x == 64 then x := 0
+//!     again
+//! ```
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::mem;
+use error::AsmError;
+
+/// Address programs are assembled for.
+const ORIGIN: u16 = 0x200;
+
+/// Words that can't be used as names.
+const KEYWORDS: [&str; 37] = [
+    "clear", "return", "scroll-down", "scroll-up", "scroll-right", "scroll-left", "exit", "lores", "hires",
+    "jump", "jump0", "i", "delay", "buzzer", "pitch", "sprite", "bcd", "save", "load", "saveflags", "loadflags",
+    "plane", "audio", "if", "then", "begin", "else", "end", "loop", "while", "again", "key", "-key", "random",
+    "hex", "bighex", "long",
+];
+
+/// Upper bound on macro expansions, which stops recursive macros.
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// An assembled program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// The machine code, to be loaded at 0x200.
+    pub rom: Vec<u8>,
+    /// The address of every label.
+    pub labels: BTreeMap<String, u16>,
+}
+
+impl Assembly {
+    /// Lists the labels ordered by address, one `ADDR name` per line.
+    pub fn symbol_map(&self) -> String {
+        let mut labels: Vec<(&String, &u16)> = self.labels.iter().collect();
+        labels.sort_by_key(|&(name, address)| (*address, name.clone()));
+        labels.iter().map(|&(name, address)| format!("{:04X} {}\n", address, name)).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error<T>(&self, message: String) -> Result<T, AsmError> {
+        Err(AsmError { line: self.line, column: self.column, message })
+    }
+}
+
+/// The jump to `address` that closes the control flow structure ended
+/// by `token`, which fails if the address doesn't fit into 12 bits.
+fn jump_to(token: &Token, address: u16) -> Result<u16, AsmError> {
+    if address > 0xFFF {
+        return token.error(format!("jump target {:04X} is out of range", address));
+    }
+    Ok(0x1000 | address)
+}
+
+/// Splits `source` into whitespace separated tokens, dropping
+/// comments, which start with `#` and run to the end of the line.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (line, text) in source.lines().enumerate() {
+        let mut start = None;
+        for (column, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+            match (c.is_whitespace(), start) {
+                (true, Some(begin)) => {
+                    tokens.push(Token { text: text[begin..column].to_string(), line: line + 1, column: begin + 1 });
+                    start = None;
+                },
+                (false, None) if c == '#' => break,
+                (false, None) => start = Some(column),
+                _ => {},
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else if digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u16> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|x| x as u16),
+        _ => None,
+    }
+}
+
+/// The skip instruction with the opposite condition.
+fn invert(skip: u16) -> u16 {
+    match skip & 0xF000 {
+        0x3000 => skip ^ 0x7000,
+        0x4000 => skip ^ 0x7000,
+        0x5000 => skip ^ 0xC000,
+        0x9000 => skip ^ 0xC000,
+        _ => skip ^ 0x003F,
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Kinds of fields a label can be referred to from before it is
+/// defined.
+#[derive(Clone, Copy)]
+enum Field {
+    /// The NNN of a 2-byte instruction.
+    Address,
+    /// The 16-bit address of F000 NNNN.
+    Long,
+}
+
+struct Fixup {
+    position: usize,
+    field: Field,
+    token: Token,
+}
+
+/// An `if ... begin` or `else` waiting for the address its jump
+/// skips to.
+struct Block {
+    jump: usize,
+    token: Token,
+    has_else: bool,
+}
+
+struct Loop {
+    start: u16,
+    /// Jumps out of the loop emitted by `while`.
+    exits: Vec<usize>,
+    token: Token,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    /// The last token taken, for errors at the end of the source.
+    last: Token,
+    rom: Vec<u8>,
+    position: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    loops: Vec<Loop>,
+}
+
+impl Assembler {
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = token.clone();
+                Ok(token)
+            },
+            None => self.last.error("unexpected end of input".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return token.error(format!("expected `{}`, found `{}`", text, token.text));
+        }
+        Ok(token)
+    }
+
+    /// The address the next byte is emitted at.
+    fn address(&self) -> Result<u16, AsmError> {
+        let address = ORIGIN as usize + self.position;
+        if address > 0xFFFF {
+            return self.last.error("program too large".to_string());
+        }
+        Ok(address as u16)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        self.address()?;
+        if self.position >= self.rom.len() {
+            self.rom.resize(self.position + 1, 0);
+        }
+        self.rom[self.position] = byte;
+        self.position += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), AsmError> {
+        self.emit_byte((word >> 8) as u8)?;
+        self.emit_byte(word as u8)
+    }
+
+    fn patch(&mut self, position: usize, word: u16) {
+        self.rom[position] = (word >> 8) as u8;
+        self.rom[position + 1] = word as u8;
+    }
+
+    fn register(&mut self) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        self.register_of(&token)
+    }
+
+    fn register_of(&self, token: &Token) -> Result<u16, AsmError> {
+        match parse_register(&token.text).or_else(|| self.aliases.get(&token.text).cloned()) {
+            Some(x) => Ok(x),
+            None => token.error(format!("expected a register, found `{}`", token.text)),
+        }
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        parse_register(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    /// The value of a number, constant or already defined label.
+    fn known_value(&self, token: &Token) -> Option<i32> {
+        parse_number(&token.text)
+            .or_else(|| self.constants.get(&token.text).cloned())
+            .or_else(|| self.labels.get(&token.text).map(|address| *address as i32))
+    }
+
+    fn value(&mut self, min: i32, max: i32) -> Result<i32, AsmError> {
+        let token = self.next()?;
+        match self.known_value(&token) {
+            Some(value) if value >= min && value <= max => Ok(value),
+            Some(value) => token.error(format!("{} is out of range {} to {}", value, min, max)),
+            None => token.error(format!("undefined name `{}`", token.text)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u16, AsmError> {
+        Ok(self.value(-128, 255)? as u16 & 0xFF)
+    }
+
+    fn nibble(&mut self) -> Result<u16, AsmError> {
+        Ok(self.value(0, 15)? as u16)
+    }
+
+    /// Emits `opcode` with an address in the field `field`, which may
+    /// be a label that is defined later.
+    fn emit_address(&mut self, opcode: u16, field: Field) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let max = match field {
+            Field::Address => 0xFFF,
+            Field::Long => 0xFFFF,
+        };
+        let address = match self.known_value(&token) {
+            Some(value) if value >= 0 && value <= max => value as u16,
+            Some(value) => return token.error(format!("address {} is out of range 0 to {}", value, max)),
+            None if self.is_name(&token.text) => {
+                self.fixups.push(Fixup { position: self.position, field, token });
+                0
+            },
+            None => return token.error(format!("expected an address, found `{}`", token.text)),
+        };
+
+        match field {
+            Field::Address => self.emit(opcode | address)?,
+            Field::Long => {
+                self.emit(opcode)?;
+                self.emit(address)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Whether `text` can name a label, constant, alias or macro.
+    fn is_name(&self, text: &str) -> bool {
+        text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+            && !KEYWORDS.contains(&text)
+            && !self.is_register(text)
+    }
+
+    /// Parses a condition and returns the skip instruction that skips
+    /// when it holds.
+    fn condition(&mut self) -> Result<u16, AsmError> {
+        let x = self.register()? << 8;
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            "key" => Ok(0xE09E | x),
+            "-key" => Ok(0xE0A1 | x),
+            "==" | "!=" => {
+                let equal = operator.text == "==";
+                if self.peek().is_some_and(|text| self.is_register(text)) {
+                    let y = self.register()? << 4;
+                    Ok(if equal { 0x5000 } else { 0x9000 } | x | y)
+                } else {
+                    let nn = self.byte()?;
+                    Ok(if equal { 0x3000 } else { 0x4000 } | x | nn)
+                }
+            },
+            _ => operator.error(format!("expected a comparison, found `{}`", operator.text)),
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+
+        if let Some(value) = parse_number(&token.text) {
+            if !(-128..=255).contains(&value) {
+                return token.error(format!("{} is out of range -128 to 255", value));
+            }
+            self.emit_byte(value as u8)?;
+            return Ok(());
+        }
+        if self.is_register(&token.text) {
+            return self.register_statement(&token);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?;
+                if !self.is_name(&name.text) {
+                    return name.error(format!("invalid label name `{}`", name.text));
+                }
+                if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+                    return name.error(format!("`{}` is already defined", name.text));
+                }
+                let address = self.address()?;
+                self.labels.insert(name.text, address);
+            },
+            ":const" => {
+                let name = self.next()?;
+                if !self.is_name(&name.text) || self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+                    return name.error(format!("cannot define `{}` as a constant", name.text));
+                }
+                let value = self.value(-0x8000, 0xFFFF)?;
+                self.constants.insert(name.text, value);
+            },
+            ":alias" => {
+                let name = self.next()?;
+                if !self.is_name(&name.text) && !self.aliases.contains_key(&name.text) {
+                    return name.error(format!("cannot use `{}` as an alias", name.text));
+                }
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+            },
+            ":macro" => self.define_macro()?,
+            ":call" => self.emit_address(0x2000, Field::Address)?,
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte as u8)?;
+            },
+            ":org" => {
+                let address = self.value(ORIGIN as i32, 0xFFFF)?;
+                self.position = (address as u16 - ORIGIN) as usize;
+            },
+            "clear" => self.emit(0x00E0)?,
+            "return" | ";" => self.emit(0x00EE)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n)?;
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n)?;
+            },
+            "scroll-right" => self.emit(0x00FB)?,
+            "scroll-left" => self.emit(0x00FC)?,
+            "exit" => self.emit(0x00FD)?,
+            "lores" => self.emit(0x00FE)?,
+            "hires" => self.emit(0x00FF)?,
+            "jump" => self.emit_address(0x1000, Field::Address)?,
+            "jump0" => self.emit_address(0xB000, Field::Address)?,
+            "i" => self.i_statement()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()? << 8;
+                self.emit(match token.text.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                } | x)?;
+            },
+            "sprite" => {
+                let x = self.register()? << 8;
+                let y = self.register()? << 4;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x | y | n)?;
+            },
+            "bcd" => {
+                let x = self.register()? << 8;
+                self.emit(0xF033 | x)?;
+            },
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    self.emit(if token.text == "save" { 0x5002 } else { 0x5003 } | x << 8 | y << 4)?;
+                } else {
+                    self.emit(if token.text == "save" { 0xF055 } else { 0xF065 } | x << 8)?;
+                }
+            },
+            "saveflags" => {
+                let x = self.register()? << 8;
+                self.emit(0xF075 | x)?;
+            },
+            "loadflags" => {
+                let x = self.register()? << 8;
+                self.emit(0xF085 | x)?;
+            },
+            "plane" => {
+                let n = self.value(0, 3)? as u16;
+                self.emit(0xF001 | n << 8)?;
+            },
+            "audio" => self.emit(0xF002)?,
+            "if" => {
+                let skip = self.condition()?;
+                let keyword = self.next()?;
+                match keyword.text.as_str() {
+                    "then" => self.emit(invert(skip))?,
+                    "begin" => {
+                        self.emit(skip)?;
+                        self.blocks.push(Block { jump: self.position, token, has_else: false });
+                        self.emit(0x1000)?;
+                    },
+                    _ => return keyword.error(format!("expected `then` or `begin`, found `{}`", keyword.text)),
+                }
+            },
+            "else" => {
+                let block = match self.blocks.pop() {
+                    Some(ref block) if block.has_else => return token.error("`else` after `else`".to_string()),
+                    Some(block) => block,
+                    None => return token.error("`else` without `if ... begin`".to_string()),
+                };
+                let jump = self.position;
+                self.emit(0x1000)?;
+                let target = jump_to(&token, self.address()?)?;
+                self.patch(block.jump, target);
+                self.blocks.push(Block { jump, token, has_else: true });
+            },
+            "end" => {
+                let block = match self.blocks.pop() {
+                    Some(block) => block,
+                    None => return token.error("`end` without `if ... begin`".to_string()),
+                };
+                let target = jump_to(&token, self.address()?)?;
+                self.patch(block.jump, target);
+            },
+            "loop" => self.loops.push(Loop { start: self.address()?, exits: Vec::new(), token }),
+            "while" => {
+                if self.loops.is_empty() {
+                    return token.error("`while` outside of `loop`".to_string());
+                }
+                let skip = self.condition()?;
+                self.emit(skip)?;
+                let position = self.position;
+                self.loops.last_mut().unwrap().exits.push(position);
+                self.emit(0x1000)?;
+            },
+            "again" => {
+                let current = match self.loops.pop() {
+                    Some(current) => current,
+                    None => return token.error("`again` without `loop`".to_string()),
+                };
+                self.emit(jump_to(&token, current.start)?)?;
+                let target = jump_to(&token, self.address()?)?;
+                for exit in current.exits {
+                    self.patch(exit, target);
+                }
+            },
+            _ if self.macros.contains_key(&token.text) => self.expand_macro(&token)?,
+            _ if token.text.starts_with(':') => return token.error(format!("unknown directive `{}`", token.text)),
+            _ if self.is_name(&token.text) => {
+                // A bare name calls the subroutine with that label.
+                self.tokens.push_front(token);
+                self.emit_address(0x2000, Field::Address)?;
+            },
+            _ => return token.error(format!("unexpected `{}`", token.text)),
+        }
+
+        Ok(())
+    }
+
+    fn register_statement(&mut self, register: &Token) -> Result<(), AsmError> {
+        let x = self.register_of(register)? << 8;
+        let operator = self.next()?;
+
+        let opcode = match operator.text.as_str() {
+            ":=" => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    0xC000 | self.byte()?
+                },
+                Some("delay") => {
+                    self.next()?;
+                    0xF007
+                },
+                Some("key") => {
+                    self.next()?;
+                    0xF00A
+                },
+                Some(text) if self.is_register(text) => 0x8000 | self.register()? << 4,
+                _ => 0x6000 | self.byte()?,
+            },
+            "+=" if !self.peek().is_some_and(|text| self.is_register(text)) => 0x7000 | self.byte()?,
+            "|=" | "&=" | "^=" | "+=" | "-=" | ">>=" | "=-" | "<<=" => {
+                let y = self.register()? << 4;
+                y | match operator.text.as_str() {
+                    "|=" => 0x8001,
+                    "&=" => 0x8002,
+                    "^=" => 0x8003,
+                    "+=" => 0x8004,
+                    "-=" => 0x8005,
+                    ">>=" => 0x8006,
+                    "=-" => 0x8007,
+                    _ => 0x800E,
+                }
+            },
+            _ => return operator.error(format!("expected an assignment, found `{}`", operator.text)),
+        };
+
+        self.emit(opcode | x)?;
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.register()? << 8;
+                    self.emit(0xF029 | x)?;
+                },
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()? << 8;
+                    self.emit(0xF030 | x)?;
+                },
+                Some("long") => {
+                    self.next()?;
+                    self.emit_address(0xF000, Field::Long)?;
+                },
+                _ => self.emit_address(0xA000, Field::Address)?,
+            },
+            "+=" => {
+                let x = self.register()? << 8;
+                self.emit(0xF01E | x)?;
+            },
+            _ => return operator.error(format!("expected `:=` or `+=`, found `{}`", operator.text)),
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.next()?;
+        if !self.is_name(&name.text) || self.macros.contains_key(&name.text) {
+            return name.error(format!("cannot define `{}` as a macro", name.text));
+        }
+
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return name.error(format!("too many macro expansions, is `{}` recursive?", name.text));
+        }
+
+        let mut args = HashMap::new();
+        for param in self.macros[&name.text].params.clone() {
+            let arg = self.next()?;
+            args.insert(param, arg);
+        }
+
+        let body = &self.macros[&name.text].body;
+        let expanded: Vec<Token> = body.iter()
+            .map(|token| args.get(&token.text).unwrap_or(token).clone())
+            .collect();
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Assembly, AsmError> {
+        if let Some(block) = self.blocks.pop() {
+            return block.token.error("`begin` without `end`".to_string());
+        }
+        if let Some(current) = self.loops.pop() {
+            return current.token.error("`loop` without `again`".to_string());
+        }
+
+        for fixup in mem::take(&mut self.fixups) {
+            let address = match self.labels.get(&fixup.token.text) {
+                Some(address) => *address,
+                None => return fixup.token.error(format!("undefined label `{}`", fixup.token.text)),
+            };
+            match fixup.field {
+                Field::Address if address > 0xFFF =>
+                    return fixup.token.error(format!("label `{}` at {:04X} is out of range", fixup.token.text, address)),
+                Field::Address => {
+                    let opcode = (self.rom[fixup.position] as u16) << 8;
+                    self.patch(fixup.position, opcode | address);
+                },
+                Field::Long => self.patch(fixup.position + 2, address),
+            }
+        }
+
+        Ok(Assembly { rom: self.rom, labels: self.labels })
+    }
+}
+
+/// Assembles Octo `source` into a program loaded at 0x200.
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source).into_iter().collect(),
+        last: Token { text: String::new(), line: 1, column: 1 },
+        rom: Vec::new(),
+        position: 0,
+        labels: BTreeMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        expansions: 0,
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        loops: Vec::new(),
+    };
+
+    while !assembler.tokens.is_empty() {
+        assembler.statement()?;
+    }
+    assembler.finish()
+}
+
+#[cfg(test)]
+fn words(source: &str) -> Vec<u16> {
+    let rom = assemble(source).unwrap().rom;
+    rom.chunks(2).map(|word| (word[0] as u16) << 8 | word[1] as u16).collect()
+}
+
+#[test]
+fn test_instructions() {
+    assert_eq!(words("clear return scroll-down 3 hires exit"), [0x00E0, 0x00EE, 0x00C3, 0x00FF, 0x00FD]);
+    assert_eq!(words("v1 := 0x23 va += 5 v2 := v3 v4 =- v5 v6 <<= v7"), [0x6123, 0x7A05, 0x8230, 0x8457, 0x867E]);
+    assert_eq!(words("v0 := random 0xFF v1 := key vf := delay v2 -= v3"), [0xC0FF, 0xF10A, 0xFF07, 0x8235]);
+    assert_eq!(words("i := 0x300 i := hex v5 i := bighex v6 i += v7"), [0xA300, 0xF529, 0xF630, 0xF71E]);
+    assert_eq!(words("sprite v1 v2 15 bcd v3 save v4 load v5 - v6"), [0xD12F, 0xF333, 0xF455, 0x5563]);
+    assert_eq!(words("delay := v1 buzzer := v2 pitch := v3 plane 3 audio"), [0xF115, 0xF218, 0xF33A, 0xF301, 0xF002]);
+    assert_eq!(words("i := long 0x1234 jump0 0x400 0x12 -1"), [0xF000, 0x1234, 0xB400, 0x12FF]);
+}
+
+#[test]
+fn test_labels_and_names() {
+    let source = "
+        :alias x v3
+        :const speed 2
+        : main
+            x += speed   # forward references are resolved at the end
+            draw
+            jump main
+        : draw
+            :call main
+            i := dot
+            return
+        : dot
+            0x80
+    ";
+    let assembly = assemble(source).unwrap();
+
+    assert_eq!(assembly.rom, [0x73, 0x02, 0x22, 0x06, 0x12, 0x00, 0x22, 0x00, 0xA2, 0x0C, 0x00, 0xEE, 0x80]);
+    assert_eq!(assembly.labels["draw"], 0x206);
+    assert_eq!(assembly.symbol_map(), "0200 main\n0206 draw\n020C dot\n");
+}
+
+#[test]
+fn test_control_flow() {
+    assert_eq!(words("if v1 == 5 then v2 := 1"), [0x4105, 0x6201]);
+    assert_eq!(words("if v1 != v2 then clear if v3 key then clear"), [0x5120, 0x00E0, 0xE3A1, 0x00E0]);
+    assert_eq!(
+        words("if v1 == 5 begin v2 := 1 else v2 := 2 end"),
+        [0x3105, 0x1208, 0x6201, 0x120A, 0x6202],
+    );
+    assert_eq!(
+        words("loop v0 += 1 while v0 != 10 clear again"),
+        [0x7001, 0x400A, 0x120A, 0x00E0, 0x1200],
+    );
+}
+
+#[test]
+fn test_macros() {
+    let source = "
+        :macro set register value { register := value }
+        :macro twice x { x x }
+        set v1 7
+        twice clear
+        :org 0x210 clear
+    ";
+    let rom = assemble(source).unwrap().rom;
+
+    assert_eq!(&rom[..6], [0x61, 0x07, 0x00, 0xE0, 0x00, 0xE0]);
+    assert_eq!(&rom[0x10..], [0x00, 0xE0]);
+}
+
+#[test]
+fn test_errors() {
+    let error = |source| assemble(source).unwrap_err();
+
+    assert_eq!(error("clear\n  v1 := 300"), AsmError { line: 2, column: 9, message: "300 is out of range -128 to 255".to_string() });
+    assert_eq!(error("jump nowhere").to_string(), "1:6: undefined label `nowhere`");
+    assert_eq!(error("v1 +=").to_string(), "1:4: unexpected end of input");
+    assert_eq!(error("\n  loop clear").to_string(), "2:3: `loop` without `again`");
+    assert_eq!(error("sprite v1 vg 1").to_string(), "1:11: expected a register, found `vg`");
+    assert_eq!(error(": a : a").to_string(), "1:7: `a` is already defined");
+    assert_eq!(error(":macro m { m } m").to_string(), "1:12: too many macro expansions, is `m` recursive?");
+    assert_eq!(error(":org 0x1000 loop clear again").to_string(), "1:24: jump target 1000 is out of range");
+    assert_eq!(error(":org 0xFFFF clear").to_string(), "1:13: program too large");
+    assert_eq!(error(":org 0xFFFE clear : x").to_string(), "1:21: program too large");
+    assert_eq!(error(":org 0xFFA if v0 == 1 begin clear end").to_string(), "1:35: jump target 1000 is out of range");
+    assert_eq!(error(":org 0xFFC if v0 == 1 begin else clear end").to_string(), "1:29: jump target 1002 is out of range");
+}
@@ -0,0 +1,58 @@
+/// Receives the state of the buzzer, which sounds as long as the sound
+/// timer is non-zero.
+pub trait AudioSink {
+    /// Called when the sound timer is set to a non-zero value while the
+    /// buzzer was silent.
+    fn start_tone(&mut self);
+    /// Called when the sound timer reaches zero.
+    fn stop_tone(&mut self);
+}
+
+/// Generates the buzzer tone as a square wave of 32 bit float samples.
+#[derive(Debug, Clone)]
+pub struct SquareWave {
+    /// Tone frequency in Hz.
+    pub frequency: f32,
+    /// Amplitude between 0.0 and 1.0.
+    pub volume: f32,
+    sample_rate: f32,
+    /// Position within the current period, between 0.0 and 1.0.
+    phase: f32,
+}
+
+impl SquareWave {
+    pub fn new(sample_rate: u32, frequency: f32, volume: f32) -> SquareWave {
+        SquareWave {
+            frequency,
+            volume,
+            sample_rate: sample_rate as f32,
+            phase: 0.0,
+        }
+    }
+
+    /// Fills `out` with the next samples of the wave.
+    pub fn render(&mut self, out: &mut [f32]) {
+        let step = self.frequency / self.sample_rate;
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 { self.volume } else { -self.volume };
+            self.phase = (self.phase + step) % 1.0;
+        }
+    }
+}
+
+#[test]
+fn test_square_wave() {
+    // Four samples per period.
+    let mut wave = SquareWave::new(1600, 400.0, 0.25);
+    let mut out = [0.0; 10];
+
+    wave.render(&mut out);
+
+    assert_eq!(out, [0.25, 0.25, -0.25, -0.25, 0.25, 0.25, -0.25, -0.25, 0.25, 0.25]);
+
+    // The phase carries over between calls.
+    let mut out = [0.0; 2];
+    wave.render(&mut out);
+
+    assert_eq!(out, [-0.25, -0.25]);
+}
@@ -0,0 +1,26 @@
+//! Assembles Octo source into a ROM.
+
+use std::fs;
+use std::path::Path;
+use chip8::asm;
+use cli::Options;
+
+/// Assembles the source file given in `args`, writing the ROM next to
+/// it unless `--output` names another path.
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = Options::parse(args, &["--output", "--symbols"])?;
+    let path = options.path("source")?;
+    let source = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    let assembly = asm::assemble(&source).map_err(|err| format!("{}:{}", path, err))?;
+
+    let output = match options.get::<String>("--output")? {
+        Some(output) => output,
+        None => Path::new(path).with_extension("ch8").to_string_lossy().into_owned(),
+    };
+    fs::write(&output, &assembly.rom).map_err(|err| format!("could not write {}: {}", output, err))?;
+
+    if let Some(symbols) = options.get::<String>("--symbols")? {
+        fs::write(&symbols, assembly.symbol_map()).map_err(|err| format!("could not write {}: {}", symbols, err))?;
+    }
+    Ok(())
+}
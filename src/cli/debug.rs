@@ -0,0 +1,290 @@
+//! An interactive debugger that runs a ROM one command at a time.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use chip8::disasm::{self, Syntax};
+use chip8::{Chip8, Instruction, StepOutcome, TIMER_FREQUENCY};
+use cli::{self, Options};
+
+/// Instructions that `continue` runs at most, as there is no way to
+/// interrupt it.
+const MAX_CONTINUE: u32 = 10_000_000;
+
+/// Instructions shown by `list` without a count.
+const LIST_LEN: usize = 10;
+
+/// Bytes shown by `examine` without a count.
+const EXAMINE_LEN: usize = 64;
+
+const HELP: &str = "Commands:
+    step [N]            s   execute N instructions, 1 by default
+    next                n   execute an instruction, running calls to completion
+    continue            c   run until a breakpoint, an error, a key wait or exit
+    break [ADDRESS]     b   set a breakpoint, or list them
+    delete ADDRESS      d   remove a breakpoint
+    registers           r   show PC, I, the timers, V0 to VF and the stack
+    examine ADDRESS [N] x   show N bytes of memory
+    set ADDRESS BYTE...     write bytes to memory
+    list [ADDRESS] [N]  l   disassemble N instructions, around PC by default
+    screen                  show the screen
+    keys [KEY...]       k   hold down the given keys and release all others
+    help                h   show this help
+    quit                q   leave the debugger
+Addresses, bytes and keys are hexadecimal, counts decimal. An empty line
+repeats the last command.";
+
+struct Debugger {
+    game: Chip8,
+    breakpoints: BTreeSet<u16>,
+    /// Instructions executed since the timers last ticked.
+    cycles: u32,
+}
+
+impl Debugger {
+    /// Executes one instruction, ticking the timers as often as they
+    /// would tick at the clock speed.
+    fn step(&mut self) -> Result<StepOutcome, String> {
+        loop {
+            let outcome = self.game.cycle().map_err(|err| err.to_string())?;
+            if outcome != StepOutcome::WaitingForVblank {
+                self.cycles += 1;
+            }
+            if outcome == StepOutcome::WaitingForVblank || self.cycles >= self.game.clock_speed() / TIMER_FREQUENCY {
+                self.game.tick_timers();
+                self.cycles = 0;
+            }
+            if outcome != StepOutcome::WaitingForVblank {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Runs until `done` returns true or something stops execution
+    /// and returns why it stopped.
+    fn run<F: Fn(&Chip8) -> bool>(&mut self, done: F) -> Result<String, String> {
+        for executed in 0..MAX_CONTINUE {
+            let pc = self.game.pc();
+            if executed > 0 && self.breakpoints.contains(&pc) {
+                return Ok(format!("breakpoint at {:03X}", pc));
+            }
+            match self.step()? {
+                StepOutcome::Exited => return Ok("program exited".to_string()),
+                StepOutcome::WaitingForKey => return Ok("waiting for a key, see `keys`".to_string()),
+                _ if self.game.pc() == pc => return Ok("stuck in a loop jumping to itself".to_string()),
+                _ if done(&self.game) => return Ok(String::new()),
+                _ => {},
+            }
+        }
+        Ok(format!("stopped after {} instructions", MAX_CONTINUE))
+    }
+
+    /// Executes one instruction, or a whole call if it is one.
+    fn next(&mut self) -> Result<String, String> {
+        let memory = self.game.memory();
+        let pc = self.game.pc() as usize;
+        let opcode = memory.get(pc..pc + 2).map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16);
+        let depth = self.game.stack().len();
+
+        if let Some(Ok(Instruction::Call(_))) = opcode.map(Instruction::decode) {
+            if self.step()? == StepOutcome::Executed {
+                if self.breakpoints.contains(&self.game.pc()) {
+                    return Ok(format!("breakpoint at {:03X}", self.game.pc()));
+                }
+                return self.run(|game| game.stack().len() <= depth);
+            }
+        } else {
+            self.step()?;
+        }
+        Ok(String::new())
+    }
+
+    /// Shows the instruction about to be executed.
+    fn where_am_i(&self) {
+        let pc = self.game.pc();
+        let instruction = disasm::instruction_at(self.game.memory(), pc, self.game.quirks().variant, Syntax::Classic);
+        println!("{:03X}  {}", pc, instruction.unwrap_or_else(|| "outside of memory".to_string()));
+    }
+
+    fn list(&self, start: Option<u16>, count: usize) {
+        let memory = self.game.memory();
+        let pc = self.game.pc();
+        // Starting a few instructions early shows where PC came from,
+        // as long as nothing in between is four bytes long.
+        let start = start.unwrap_or_else(|| pc.saturating_sub(6) & !1) as usize;
+        let count = count.min(memory.len());
+        let end = (start + count * 2 + 2).min(memory.len());
+        if start >= end {
+            return;
+        }
+
+        let lines = disasm::disassemble(&memory[start..end], start as u16, self.game.quirks().variant);
+        for line in lines.iter().take(count) {
+            let bytes: String = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            println!("{}{} {:03X}  {:8}  {}",
+                     if line.address == pc { '>' } else { ' ' },
+                     if self.breakpoints.contains(&line.address) { '*' } else { ' ' },
+                     line.address, bytes, line.format(Syntax::Classic, &BTreeSet::new()));
+        }
+    }
+
+    /// Prints the screen with a character for each combination of
+    /// XO-CHIP planes a pixel is set in.
+    fn screen(&self) {
+        for row in self.game.display().rows() {
+            let row: String = row.iter().map(|pixel| match *pixel {
+                0 => '.',
+                1 => '#',
+                2 => '+',
+                _ => '@',
+            }).collect();
+            println!("{}", row);
+        }
+    }
+
+    /// The memory range of `len` bytes at `address`, if it exists.
+    fn range(&self, address: u16, len: usize) -> Result<(usize, usize), String> {
+        let start = address as usize;
+        match start.checked_add(len) {
+            Some(end) if end <= self.game.memory().len() => Ok((start, end)),
+            _ => Err(format!("{} bytes at {:04X} are outside of memory", len, start)),
+        }
+    }
+
+    /// Carries out `command` and returns false once the debugger
+    /// should quit.
+    fn command(&mut self, command: &[&str]) -> Result<bool, String> {
+        let count = |index: usize, default: usize| match command.get(index) {
+            Some(count) => count.parse().map_err(|_| format!("invalid count: {}", count)),
+            None => Ok(default),
+        };
+        let address = |index: usize| match command.get(index) {
+            Some(address) => cli::parse_address(address),
+            None => Err("missing address".to_string()),
+        };
+
+        let stop = match command[0] {
+            "step" | "s" => {
+                for _ in 0..count(1, 1)? {
+                    if self.step()? == StepOutcome::Exited {
+                        break;
+                    }
+                }
+                String::new()
+            },
+            "next" | "n" => self.next()?,
+            "continue" | "c" => self.run(|_| false)?,
+            "break" | "b" if command.len() == 1 => {
+                for breakpoint in self.breakpoints.iter() {
+                    println!("{:03X}", breakpoint);
+                }
+                return Ok(true);
+            },
+            "break" | "b" => {
+                self.breakpoints.insert(address(1)?);
+                return Ok(true);
+            },
+            "delete" | "d" => {
+                if !self.breakpoints.remove(&address(1)?) {
+                    return Err(format!("no breakpoint at {}", command[1]));
+                }
+                return Ok(true);
+            },
+            "registers" | "r" => {
+                let stdout = io::stdout();
+                cli::write_registers(&mut stdout.lock(), &self.game).map_err(|err| err.to_string())?;
+                return Ok(true);
+            },
+            "examine" | "x" => {
+                let (start, end) = self.range(address(1)?, count(2, EXAMINE_LEN)?)?;
+                let stdout = io::stdout();
+                cli::write_hexdump(&mut stdout.lock(), &self.game.memory()[start..end], start)
+                    .map_err(|err| err.to_string())?;
+                return Ok(true);
+            },
+            "set" => {
+                if command.len() < 3 {
+                    return Err("usage: set ADDRESS BYTE...".to_string());
+                }
+                let bytes = command[2..].iter()
+                    .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| format!("invalid byte: {}", byte)))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                let (start, end) = self.range(address(1)?, bytes.len())?;
+                self.game.memory_mut()[start..end].copy_from_slice(&bytes);
+                return Ok(true);
+            },
+            "list" | "l" => {
+                let start = if command.len() > 1 { Some(address(1)?) } else { None };
+                self.list(start, count(2, LIST_LEN)?);
+                return Ok(true);
+            },
+            "screen" => {
+                self.screen();
+                return Ok(true);
+            },
+            "keys" | "k" => {
+                let mut keys = 0;
+                for key in command[1..].iter() {
+                    match u8::from_str_radix(key, 16) {
+                        Ok(key) if key < 16 => keys |= 1 << key,
+                        _ => return Err(format!("invalid key: {}", key)),
+                    }
+                }
+                self.game.set_keypad(keys);
+                return Ok(true);
+            },
+            "help" | "h" => {
+                println!("{}", HELP);
+                return Ok(true);
+            },
+            "quit" | "q" => return Ok(false),
+            other => return Err(format!("unknown command: {}, try `help`", other)),
+        };
+
+        if !stop.is_empty() {
+            println!("{}", stop);
+        }
+        self.where_am_i();
+        Ok(true)
+    }
+}
+
+/// Reads debugger commands from standard input until `quit` or the end
+/// of input.
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = Options::parse(args, &["--quirks", "--load-address"])?;
+    let path = options.rom()?;
+    let mut game = Chip8::with_quirks(options.quirks()?);
+    if let Some(address) = options.get::<String>("--load-address")? {
+        game.set_load_address(cli::parse_address(&address)?);
+    }
+    game.load_file(path).map_err(|err| format!("could not load {}: {}", path, err))?;
+
+    let mut debugger = Debugger { game, breakpoints: BTreeSet::new(), cycles: 0 };
+    debugger.where_am_i();
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut last = String::new();
+    loop {
+        print!("(chip8) ");
+        io::stdout().flush().map_err(|err| err.to_string())?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(|err| err.to_string())?,
+            None => return Ok(()),
+        };
+        if !line.trim().is_empty() {
+            last = line;
+        }
+
+        let command: Vec<&str> = last.split_whitespace().collect();
+        if command.is_empty() {
+            continue;
+        }
+        match debugger.command(&command) {
+            Ok(true) => {},
+            Ok(false) => return Ok(()),
+            Err(err) => println!("error: {}", err),
+        }
+    }
+}
+
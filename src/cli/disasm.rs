@@ -0,0 +1,28 @@
+//! Prints the disassembly of a ROM.
+
+use chip8::disasm::{self, Syntax};
+use chip8::Quirks;
+use cli::{self, Options};
+
+/// Disassembles the ROM given in `args` to standard output.
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = Options::parse(args, &["--syntax", "--quirks", "--base"])?;
+    let rom = cli::read_rom(options.rom()?)?;
+    let syntax = match options.get::<String>("--syntax")?.as_deref() {
+        None | Some("classic") => Syntax::Classic,
+        Some("octo") => Syntax::Octo,
+        Some(other) => return Err(format!("unknown syntax: {}", other)),
+    };
+    // Without a preset, every instruction of any variant is decoded.
+    let variant = match options.get::<String>("--quirks")? {
+        Some(_) => options.quirks()?.variant,
+        None => Quirks::xochip().variant,
+    };
+    let base = match options.get::<String>("--base")? {
+        Some(base) => cli::parse_address(&base)?,
+        None => 0x200,
+    };
+
+    print!("{}", disasm::listing(&rom, base, variant, syntax));
+    Ok(())
+}
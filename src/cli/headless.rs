@@ -0,0 +1,171 @@
+//! Runs a ROM without a window for a fixed number of frames, for
+//! scripted tests and screenshots.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use chip8::{Chip8, Key, Movie, RomInfo, Trace, DEFAULT_CLOCK_SPEED, TIMER_FREQUENCY};
+use cli::{self, Options};
+
+const DEFAULT_FRAMES: usize = 600;
+
+/// A change of a key's state at the start of a frame.
+struct KeyEvent {
+    frame: usize,
+    key: Key,
+    down: bool,
+}
+
+/// Parses a key script of `FRAME:+K` (press) and `FRAME:-K` (release)
+/// events separated by commas or whitespace, where `K` is the hex
+/// value of the key. A script starting with `@` is read from the
+/// named file instead.
+fn parse_keys(script: &str) -> Result<Vec<KeyEvent>, String> {
+    let script = if let Some(path) = script.strip_prefix('@') {
+        fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?
+    } else {
+        script.to_string()
+    };
+
+    let mut events = Vec::new();
+    for event in script.split(|c: char| c == ',' || c.is_whitespace()).filter(|event| !event.is_empty()) {
+        let invalid = || format!("invalid key event: {}", event);
+        let colon = event.find(':').ok_or_else(invalid)?;
+        let frame = event[..colon].parse().map_err(|_| invalid())?;
+        let action = &event[colon + 1..];
+        let down = match action.chars().next() {
+            Some('+') => true,
+            Some('-') => false,
+            _ => return Err(invalid()),
+        };
+        let key = u8::from_str_radix(&action[1..], 16).ok()
+            .and_then(Key::from_index)
+            .ok_or_else(invalid)?;
+        events.push(KeyEvent { frame, key, down });
+    }
+
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+fn write_dump<W: Write>(out: &mut W, game: &Chip8, frames: usize) -> io::Result<()> {
+    writeln!(out, "frames: {}", frames)?;
+    cli::write_registers(out, game)?;
+    writeln!(out, "memory:")?;
+    cli::write_hexdump(out, game.memory(), 0)
+}
+
+/// Parses a `START-END` range of hexadecimal addresses.
+fn parse_range(range: &str) -> Result<(u16, u16), String> {
+    let dash = range.find('-').ok_or_else(|| format!("invalid address range: {}", range))?;
+    Ok((cli::parse_address(&range[..dash])?, cli::parse_address(&range[dash + 1..])?))
+}
+
+/// Opens `path` for writing, where `-` stands for standard output.
+fn create(path: &str) -> Result<Box<dyn Write>, String> {
+    if path == "-" {
+        Ok(Box::new(io::stdout()))
+    } else {
+        let file = File::create(path).map_err(|err| format!("could not create {}: {}", path, err))?;
+        Ok(Box::new(io::BufWriter::new(file)))
+    }
+}
+
+/// Runs the ROM given in `args` and writes the requested outputs.
+///
+/// Unless given, the quirks and clock speed are taken from the ROM
+/// database if it knows the ROM.
+///
+/// With `--record`, the run is also saved as a movie with a checkpoint
+/// at the end. With `--replay`, the keys of a movie are played back
+/// instead of the key script and its checkpoints are verified. With
+/// `--trace`, every executed instruction is logged.
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = Options::parse(args, &[
+        "--frames", "--hz", "--quirks", "--database", "--seed", "--keys", "--screenshot", "--dump", "--record", "--replay",
+        "--trace", "--trace-range", "--trace-limit",
+    ])?;
+    let rom = cli::read_rom(options.rom()?)?;
+    let keys = match options.get::<String>("--keys")? {
+        Some(script) => parse_keys(&script)?,
+        None => Vec::new(),
+    };
+
+    let replay = options.get::<String>("--replay")?;
+    let mut movie = match replay {
+        Some(ref path) => cli::read_movie(path)?,
+        None => {
+            let info = cli::rom_info(&options, &rom)?;
+            let clock_speed = info.as_ref().and_then(RomInfo::clock_speed).unwrap_or(DEFAULT_CLOCK_SPEED);
+            let clock_speed = options.get_or("--hz", clock_speed)?;
+            let quirks = options.quirks_or(info.and_then(|info| info.quirks).unwrap_or_default())?;
+            let seed = options.get_or("--seed", Chip8::new().seed())?;
+            Movie::new(&rom, quirks, seed, clock_speed / TIMER_FREQUENCY)
+        },
+    };
+    let replaying = replay.is_some();
+    let frames = if replaying {
+        options.get_or("--frames", movie.frames.len())?.min(movie.frames.len())
+    } else {
+        options.get_or("--frames", DEFAULT_FRAMES)?
+    };
+    let mut game = movie.start(&rom).map_err(|err| err.to_string())?;
+    let trace = options.get::<String>("--trace")?;
+    if let Some(ref path) = trace {
+        let mut trace = Trace::new(create(path)?);
+        if let Some(range) = options.get::<String>("--trace-range")? {
+            let (start, end) = parse_range(&range)?;
+            trace = trace.addresses(start..=end);
+        }
+        if let Some(limit) = options.get("--trace-limit")? {
+            trace = trace.limit(limit);
+        }
+        game.set_trace(trace);
+    }
+
+    // An error stops the emulation, but the outputs are still written
+    // to help find out what went wrong.
+    let mut result = Ok(());
+    let mut frame = 0;
+    let mut events = keys.iter().peekable();
+    while frame < frames && !game.has_exited() {
+        let step = if replaying {
+            movie.play_frame(&mut game, frame).map_err(|err| err.to_string())
+        } else {
+            while let Some(event) = events.peek().filter(|event| event.frame <= frame) {
+                if event.down {
+                    game.key_down(event.key);
+                } else {
+                    game.key_up(event.key);
+                }
+                events.next();
+            }
+            movie.record_frame(&mut game).map_err(|err| err.to_string())
+        };
+
+        if let Err(err) = step {
+            result = Err(err);
+            break;
+        }
+        frame += 1;
+    }
+
+    if let (Some(path), Some(trace)) = (trace, game.take_trace()) {
+        trace.finish().map_err(|err| format!("could not write {}: {}", path, err))?;
+    }
+    if let Some(path) = options.get::<String>("--screenshot")? {
+        let mut out = create(&path)?;
+        game.write_pbm(&mut out).and_then(|_| out.flush())
+            .map_err(|err| format!("could not write {}: {}", path, err))?;
+    }
+    if let Some(path) = options.get::<String>("--dump")? {
+        let mut out = create(&path)?;
+        write_dump(&mut out, &game, frame).and_then(|_| out.flush())
+            .map_err(|err| format!("could not write {}: {}", path, err))?;
+    }
+    if let Some(path) = options.get::<String>("--record")? {
+        movie.add_checkpoint(&game);
+        cli::write_movie(&path, &movie)?;
+    }
+
+    result
+}
@@ -0,0 +1,161 @@
+//! The subcommands of the chip8 binary and the helpers they share.
+
+pub mod asm;
+pub mod debug;
+pub mod disasm;
+pub mod headless;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+pub mod term;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use chip8::{Chip8, Movie, Quirks, RomDatabase, RomInfo};
+
+/// Command line arguments of a subcommand, split into `--name value`
+/// options and positional arguments.
+pub struct Options {
+    values: HashMap<String, String>,
+    positional: Vec<String>,
+}
+
+impl Options {
+    /// Parses `args`, accepting only the options named in `valued`.
+    pub fn parse(args: Vec<String>, valued: &[&str]) -> Result<Options, String> {
+        let mut options = Options {
+            values: HashMap::new(),
+            positional: Vec::new(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if valued.contains(&arg.as_str()) {
+                let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+                options.values.insert(arg, value);
+            } else if arg.starts_with("--") {
+                return Err(format!("unknown option: {}", arg));
+            } else {
+                options.positional.push(arg);
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// The value of option `name` parsed as `T`, if it was given.
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.values.get(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| format!("invalid value for {}: {}", name, value)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        self.get(name).map(|value| value.unwrap_or(default))
+    }
+
+    /// The single positional argument, which is the ROM path for
+    /// most subcommands.
+    pub fn rom(&self) -> Result<&str, String> {
+        self.path("ROM")
+    }
+
+    /// The single positional argument, a path to what `kind` names.
+    pub fn path(&self, kind: &str) -> Result<&str, String> {
+        match self.positional.len() {
+            0 => Err(format!("missing {} path", kind)),
+            1 => Ok(&self.positional[0]),
+            _ => Err(format!("unexpected argument: {}", self.positional[1])),
+        }
+    }
+
+    /// The quirks preset selected with `--quirks`, the COSMAC VIP
+    /// behaviour by default.
+    pub fn quirks(&self) -> Result<Quirks, String> {
+        self.quirks_or(Quirks::default())
+    }
+
+    /// The quirks preset selected with `--quirks`, else `default`.
+    pub fn quirks_or(&self, default: Quirks) -> Result<Quirks, String> {
+        match self.values.get("--quirks") {
+            Some(name) => Quirks::preset(name).ok_or_else(|| format!("unknown quirks preset: {}", name)),
+            None => Ok(default),
+        }
+    }
+}
+
+/// Parses a hexadecimal address, with or without a `0x` prefix.
+pub fn parse_address(value: &str) -> Result<u16, String> {
+    let digits = value.trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address: {}", value))
+}
+
+/// Reads the whole ROM at `path`.
+pub fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("could not read {}: {}", path, err))
+}
+
+/// Where the user's own ROM database is read from:
+/// `chip8/programs.json` in `$XDG_CONFIG_HOME`, or else in `~/.config`.
+fn user_database_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("chip8").join("programs.json"))
+}
+
+fn read_database(path: &Path) -> Result<RomDatabase, String> {
+    let json = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+    RomDatabase::parse(&json).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// What the ROM database knows about `rom`.
+///
+/// The embedded database is overridden by the user's database, if
+/// there is one, which in turn is overridden by the one given with
+/// `--database`.
+pub fn rom_info(options: &Options, rom: &[u8]) -> Result<Option<RomInfo>, String> {
+    let mut database = RomDatabase::embedded();
+    if let Some(path) = user_database_path().filter(|path| path.exists()) {
+        database.merge(read_database(&path)?);
+    }
+    if let Some(path) = options.get::<String>("--database")? {
+        database.merge(read_database(Path::new(&path))?);
+    }
+    Ok(database.lookup(rom).cloned())
+}
+
+/// Reads the movie at `path`.
+pub fn read_movie(path: &str) -> Result<Movie, String> {
+    let data = fs::read(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    Movie::from_bytes(&data).map_err(|err| format!("{}: {}", path, err))
+}
+
+pub fn write_movie(path: &str, movie: &Movie) -> Result<(), String> {
+    fs::write(path, movie.to_bytes()).map_err(|err| format!("could not write {}: {}", path, err))
+}
+
+/// Writes the program counter, I, the timers, the registers and the
+/// stack of `game`.
+pub fn write_registers<W: Write>(out: &mut W, game: &Chip8) -> io::Result<()> {
+    writeln!(out, "PC: {:04X}  I: {:04X}  SP: {:X}  DT: {:02X}  ST: {:02X}",
+             game.pc(), game.i(), game.stack().len(), game.delay_timer(), game.sound_timer())?;
+    for (x, value) in game.v().iter().enumerate() {
+        write!(out, "V{:X}: {:02X}{}", x, value, if x % 8 == 7 { "\n" } else { "  " })?;
+    }
+    let stack: Vec<String> = game.stack().iter().map(|address| format!("{:04X}", address)).collect();
+    writeln!(out, "stack: [{}]", stack.join(" "))
+}
+
+/// Writes `bytes` 16 to a line, each line starting with its address
+/// counted from `start`.
+pub fn write_hexdump<W: Write>(out: &mut W, bytes: &[u8], start: usize) -> io::Result<()> {
+    for (row, bytes) in bytes.chunks(16).enumerate() {
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(out, "{:04X}: {}", start + row * 16, bytes.join(" "))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+//! The native player, which runs a ROM in an SDL2 window.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::rect::Rect;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::EventPump;
+use chip8::{AudioSink, Chip8, Key, Movie, RomInfo, SquareWave, DEFAULT_CLOCK_SPEED, TIMER_FREQUENCY};
+use cli::{self, Options};
+
+#[cfg(target_os = "emscripten")]
+extern "C" {
+    fn emscripten_sleep(ms: std::os::raw::c_uint);
+}
+
+const DEFAULT_SCALE: u32 = 10;
+const DEFAULT_TONE: f32 = 440.0;
+const DEFAULT_VOLUME: f32 = 0.25;
+const SAMPLE_RATE: i32 = 44100;
+/// Seconds of play that can be rewound by default.
+const DEFAULT_REWIND: u32 = 120;
+/// Rewinds while held.
+const REWIND_KEY: Scancode = Scancode::Backspace;
+
+/// Longest stretch of time emulated in one go, so that the emulator
+/// doesn't try to catch up after the window was dragged or suspended.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// Maps the conventional QWERTY layout to the hex keypad:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  ->  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+const KEY_MAP: [(Scancode, Key); 16] = [
+    (Scancode::Num1, Key::Num1), (Scancode::Num2, Key::Num2), (Scancode::Num3, Key::Num3), (Scancode::Num4, Key::C),
+    (Scancode::Q, Key::Num4), (Scancode::W, Key::Num5), (Scancode::E, Key::Num6), (Scancode::R, Key::D),
+    (Scancode::A, Key::Num7), (Scancode::S, Key::Num8), (Scancode::D, Key::Num9), (Scancode::F, Key::E),
+    (Scancode::Z, Key::A), (Scancode::X, Key::Num0), (Scancode::C, Key::B), (Scancode::V, Key::F),
+];
+
+/// Keys for the inputs the ROM database names for a ROM, which are
+/// mapped in addition to `KEY_MAP`.
+const INPUT_MAP: [(&str, Scancode); 6] = [
+    ("up", Scancode::Up), ("down", Scancode::Down), ("left", Scancode::Left), ("right", Scancode::Right),
+    ("a", Scancode::Space), ("b", Scancode::Return),
+];
+
+/// Colours for the four XO-CHIP plane combinations. Outside of XO-CHIP
+/// only the first two are used.
+const PALETTE: [(u8, u8, u8); 4] = [
+    (0x00, 0x00, 0x00),
+    (0xFF, 0xFF, 0xFF),
+    (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55),
+];
+
+/// Feeds the buzzer tone to SDL's audio thread.
+struct Tone(SquareWave);
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.render(out);
+    }
+}
+
+/// Plays the tone while the sound timer is active.
+struct Buzzer(AudioDevice<Tone>);
+
+impl AudioSink for Buzzer {
+    fn start_tone(&mut self) {
+        self.0.resume();
+    }
+
+    fn stop_tone(&mut self) {
+        self.0.pause();
+    }
+}
+
+fn set_keys(event_pump: &EventPump, key_map: &[(Scancode, Key)], game: &mut Chip8) {
+    let keyboard = event_pump.keyboard_state();
+    let mut keys = 0;
+    for &(scancode, key) in key_map.iter() {
+        if keyboard.is_scancode_pressed(scancode) {
+            keys |= 1 << key.index();
+        }
+    }
+    game.set_keypad(keys);
+}
+
+fn draw(canvas: &mut Canvas<Window>, game: &Chip8, palette: &[(u8, u8, u8); 4]) {
+    let (width, height) = (game.width() as u32, game.height() as u32);
+    if canvas.logical_size() != (width, height) {
+        canvas.set_logical_size(width, height).unwrap();
+    }
+
+    let (r, g, b) = palette[0];
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    canvas.clear();
+
+    for (y, row) in game.display().rows().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            if *pixel != 0 {
+                let (r, g, b) = palette[*pixel as usize & 0b11];
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                canvas.fill_rect(Rect::new(x as i32, y as i32, 1, 1)).unwrap();
+            }
+        }
+    }
+
+    canvas.present();
+}
+
+#[cfg(not(target_os = "emscripten"))]
+fn sleep(duration: Duration) {
+    std::thread::sleep(duration);
+}
+
+/// The browser only gets a chance to run while the emulator sleeps
+/// through emscripten, which requires building with ASYNCIFY.
+#[cfg(target_os = "emscripten")]
+fn sleep(duration: Duration) {
+    unsafe {
+        emscripten_sleep(duration.subsec_nanos() / 1_000_000);
+    }
+}
+
+/// Plays the ROM given in `args` until the window is closed.
+///
+/// Settings that aren't given are taken from the ROM database if it
+/// knows the ROM, including the colours and the keys for its inputs.
+///
+/// With `--record` or `--replay`, the emulator runs exactly one frame
+/// per displayed frame and rewinding and loading states are disabled,
+/// so that the movie can be replayed exactly.
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = Options::parse(args, &[
+        "--scale", "--hz", "--quirks", "--database", "--tone", "--volume", "--rewind", "--record", "--replay",
+    ])?;
+    let rom = options.rom()?;
+    let scale = options.get_or("--scale", DEFAULT_SCALE)?;
+    let tone = options.get_or("--tone", DEFAULT_TONE)?;
+    let volume = options.get_or("--volume", DEFAULT_VOLUME)?;
+
+    if scale == 0 {
+        return Err("scale must be at least 1".to_string());
+    }
+    if !(0.0..=1.0).contains(&volume) {
+        return Err("volume must be between 0 and 1".to_string());
+    }
+
+    let record = options.get::<String>("--record")?;
+    let replay = options.get::<String>("--replay")?;
+    let free_play = record.is_none() && replay.is_none();
+
+    let rom_data = cli::read_rom(rom)?;
+    let info = cli::rom_info(&options, &rom_data)?;
+    let clock_speed = info.as_ref().and_then(RomInfo::clock_speed).unwrap_or(DEFAULT_CLOCK_SPEED);
+    let clock_speed = options.get_or("--hz", clock_speed)?;
+    let quirks = options.quirks_or(info.as_ref().and_then(|info| info.quirks).unwrap_or_default())?;
+    let mut movie = match replay {
+        Some(ref path) => cli::read_movie(path)?,
+        None => Movie::new(&rom_data, quirks, Chip8::new().seed(), clock_speed / TIMER_FREQUENCY),
+    };
+    let mut game = movie.start(&rom_data).map_err(|err| err.to_string())?;
+    if free_play {
+        game.set_clock_speed(clock_speed);
+        game.enable_rewind(options.get_or("--rewind", DEFAULT_REWIND)? as usize * TIMER_FREQUENCY as usize);
+    }
+
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let mut event_pump = sdl_context.event_pump()?;
+
+    let desired_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+    let device = sdl_context.audio().and_then(|audio| {
+        audio.open_playback(None, &desired_spec, |spec| {
+            Tone(SquareWave::new(spec.freq as u32, tone, volume))
+        })
+    });
+    match device {
+        Ok(device) => game.set_audio_sink(Box::new(Buzzer(device))),
+        Err(err) => eprintln!("Sound is disabled: {}", err),
+    }
+
+    let mut palette = PALETTE;
+    let mut key_map = KEY_MAP.to_vec();
+    if let Some(ref info) = info {
+        for (color, &db_color) in palette.iter_mut().zip(info.colors.iter()) {
+            *color = db_color;
+        }
+        for &(input, scancode) in INPUT_MAP.iter() {
+            if let Some(key) = info.key(input) {
+                key_map.push((scancode, key));
+            }
+        }
+    }
+
+    let title = match info {
+        Some(ref info) => format!("CHIP-8 - {}", info.title),
+        None => Path::new(rom).file_name()
+            .map(|name| format!("CHIP-8 - {}", name.to_string_lossy()))
+            .unwrap_or_else(|| "CHIP-8".to_string()),
+    };
+    let window = video_subsystem
+        .window(&title, game.width() as u32 * scale, game.height() as u32 * scale)
+        .position_centered()
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    // Let's create a Canvas which we will use to draw in our Window
+    let mut canvas: Canvas<Window> = window.into_canvas()
+        .present_vsync() //< this means the screen cannot
+        // render faster than your display rate (usually 60Hz or 144Hz)
+        .build().map_err(|err| err.to_string())?;
+
+    let state_path = format!("{}.state", rom);
+    let frame_time = Duration::from_secs(1) / TIMER_FREQUENCY;
+    let mut last_frame = Instant::now();
+    let mut movie_frame = 0;
+    let mut result = Ok(());
+    // Whether the window needs to be drawn even if the screen didn't
+    // change, e.g. after it was uncovered.
+    let mut redraw = true;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                    match fs::write(&state_path, game.save_state()) {
+                        Ok(()) => println!("Saved state to {}", state_path),
+                        Err(err) => eprintln!("Could not save state to {}: {}", state_path, err),
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } if free_play => {
+                    let result = fs::read(&state_path).map_err(|err| err.to_string())
+                        .and_then(|state| game.load_state(&state).map_err(|err| err.to_string()));
+                    if let Err(err) = result {
+                        eprintln!("Could not load state from {}: {}", state_path, err);
+                    }
+                },
+                Event::Window { .. } => redraw = true,
+                _ => {},
+            }
+        }
+        set_keys(&event_pump, &key_map, &mut game);
+
+        let now = Instant::now();
+        let elapsed = (now - last_frame).min(MAX_FRAME_TIME);
+        last_frame = now;
+
+        let step = if replay.is_some() {
+            // The last frame stays on screen once the movie is over.
+            if movie_frame < movie.frames.len() {
+                movie_frame += 1;
+                movie.play_frame(&mut game, movie_frame - 1).map_err(|err| err.to_string())
+            } else {
+                Ok(())
+            }
+        } else if record.is_some() {
+            let step = movie.record_frame(&mut game).map_err(|err| err.to_string());
+            if movie.frames.len() % TIMER_FREQUENCY as usize == 0 {
+                movie.add_checkpoint(&game);
+            }
+            step
+        } else if event_pump.keyboard_state().is_scancode_pressed(REWIND_KEY) {
+            // Goes back in time at the speed the game runs forward.
+            game.rewind(1);
+            Ok(())
+        } else {
+            game.step_for(elapsed).map_err(|err| err.to_string())
+        };
+        if step.is_err() {
+            result = step;
+            break;
+        }
+        if game.has_exited() {
+            break;
+        }
+
+        if game.display_mut().take_dirty().is_some() || redraw {
+            draw(&mut canvas, &game, &palette);
+            redraw = false;
+        }
+
+        // Without vsync, presenting returns immediately.
+        let spent = Instant::now() - now;
+        if spent < frame_time {
+            sleep(frame_time - spent);
+        }
+    }
+
+    if let Some(path) = record {
+        movie.add_checkpoint(&game);
+        cli::write_movie(&path, &movie)?;
+    }
+
+    result
+}
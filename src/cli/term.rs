@@ -0,0 +1,183 @@
+//! A player that runs a ROM in the terminal, drawing the screen with
+//! Unicode half blocks or braille characters. It needs nothing but a
+//! terminal that understands ANSI escape sequences and `stty` to
+//! switch it into raw mode, so it works over SSH and without SDL.
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use chip8::terminal::{self, Input, Keys, INPUT_MAP};
+use chip8::{AudioSink, Chip8, Display, Key, RomInfo, DEFAULT_CLOCK_SPEED, TIMER_FREQUENCY};
+use cli::{self, Options};
+
+/// Frames a key stays down after it was first typed, a little longer
+/// than the usual delay of 250 to 660 ms before terminals repeat a key
+/// that is held down.
+const DEFAULT_HOLD: u32 = 40;
+
+/// Longest stretch of time emulated in one go, see the SDL player.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// Colours for the four XO-CHIP plane combinations, like the SDL player.
+const PALETTE: [(u8, u8, u8); 4] = [
+    (0x00, 0x00, 0x00),
+    (0xFF, 0xFF, 0xFF),
+    (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Render {
+    /// Two pixels per character, one above the other, in colour.
+    HalfBlocks,
+    /// Two by four pixels per character, without colour.
+    Braille,
+}
+
+/// Runs `stty` on the terminal and returns what it printed.
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()
+        .map_err(|err| format!("could not run stty: {}", err))?;
+    if !output.status.success() {
+        return Err(format!("stty failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Puts the terminal into raw mode on the alternate screen for as
+/// long as it lives.
+struct Terminal {
+    /// The settings to restore, as printed by `stty -g`.
+    saved: String,
+}
+
+impl Terminal {
+    fn raw() -> Result<Terminal, String> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        // Reads return immediately, with or without input.
+        stty(&["raw", "-echo", "min", "0", "time", "0"])?;
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        Ok(Terminal { saved })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Rings the terminal bell when the buzzer starts.
+struct Bell;
+
+impl AudioSink for Bell {
+    fn start_tone(&mut self) {
+        print!("\x07");
+    }
+
+    fn stop_tone(&mut self) {}
+}
+
+fn draw(display: &Display, render: Render, palette: &[(u8, u8, u8); 4], clear: bool) -> io::Result<()> {
+    // The screen is only cleared when the resolution changed, which
+    // avoids flicker.
+    let mut out = String::from(if clear { "\x1b[2J\x1b[H" } else { "\x1b[H" });
+    match render {
+        Render::HalfBlocks => terminal::half_blocks(display, palette, &mut out),
+        Render::Braille => terminal::braille(display, &mut out),
+    }
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(out.as_bytes())?;
+    stdout.flush()
+}
+
+/// Plays the ROM given in `args` in the terminal until it exits or the
+/// user presses Ctrl-C or Escape.
+///
+/// Settings that aren't given are taken from the ROM database, like in
+/// the SDL player.
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = Options::parse(args, &["--hz", "--quirks", "--database", "--render", "--hold"])?;
+    let rom = cli::read_rom(options.rom()?)?;
+    let render = match options.get_or("--render", "half".to_string())?.as_str() {
+        "half" => Render::HalfBlocks,
+        "braille" => Render::Braille,
+        other => return Err(format!("unknown rendering: {}, expected half or braille", other)),
+    };
+    let hold = options.get_or("--hold", DEFAULT_HOLD)?;
+    if hold == 0 {
+        return Err("hold must be at least 1 frame".to_string());
+    }
+
+    let info = cli::rom_info(&options, &rom)?;
+    let clock_speed = info.as_ref().and_then(RomInfo::clock_speed).unwrap_or(DEFAULT_CLOCK_SPEED);
+    let clock_speed = options.get_or("--hz", clock_speed)?;
+    let quirks = options.quirks_or(info.as_ref().and_then(|info| info.quirks).unwrap_or_default())?;
+    let mut game = Chip8::with_quirks(quirks);
+    game.load_rom(&rom).map_err(|err| err.to_string())?;
+    game.set_clock_speed(clock_speed);
+    game.set_audio_sink(Box::new(Bell));
+
+    let mut palette = PALETTE;
+    let mut inputs: Vec<(&'static [u8], Key)> = Vec::new();
+    if let Some(ref info) = info {
+        for (color, &db_color) in palette.iter_mut().zip(info.colors.iter()) {
+            *color = db_color;
+        }
+        for &(input, sequence) in INPUT_MAP.iter() {
+            if let Some(key) = info.key(input) {
+                inputs.push((sequence, key));
+            }
+        }
+    }
+
+    let terminal = Terminal::raw()?;
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let mut buffer = [0; 64];
+    let mut input = Input::new(inputs);
+    let mut keys = Keys::new(hold);
+    let mut size = (0, 0);
+
+    let frame_time = Duration::from_secs(1) / TIMER_FREQUENCY;
+    let mut last_frame = Instant::now();
+    let result = loop {
+        let read = stdin.read(&mut buffer).map_err(|err| err.to_string())?;
+        if !input.handle(&buffer[..read], &mut keys) {
+            break Ok(());
+        }
+        game.set_keypad(keys.keypad());
+
+        let now = Instant::now();
+        let elapsed = (now - last_frame).min(MAX_FRAME_TIME);
+        last_frame = now;
+
+        if let Err(err) = game.step_for(elapsed) {
+            break Err(err.to_string());
+        }
+        if game.has_exited() {
+            break Ok(());
+        }
+
+        if game.display_mut().take_dirty().is_some() {
+            let display = game.display();
+            let clear = size != (display.width(), display.height());
+            size = (display.width(), display.height());
+            draw(display, render, &palette, clear).map_err(|err| err.to_string())?;
+        }
+        keys.next_frame();
+
+        let spent = Instant::now() - now;
+        if spent < frame_time {
+            thread::sleep(frame_time - spent);
+        }
+    };
+
+    drop(terminal);
+    result
+}
+
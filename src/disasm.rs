@@ -0,0 +1,296 @@
+//! Turns CHIP-8, SUPER-CHIP and XO-CHIP machine code back into
+//! assembly, either in the classic syntax of Cowgod's reference
+//! (`LD V1, #23`) or in Octo's (`v1 := 0x23`).
+//!
+//! Disassembly is a linear sweep: every word that decodes to an
+//! instruction of the variant is shown as one, everything else as
+//! data. Addresses that are jumped to or called get a label.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use instruction::Instruction;
+use quirks::Variant;
+
+/// Assembly syntax to disassemble to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// The mnemonics of Cowgod's Chip-8 technical reference, with
+    /// hexadecimal numbers prefixed by `#`.
+    Classic,
+    /// The syntax of the Octo assembler. Listings in it can be
+    /// assembled again.
+    Octo,
+}
+
+/// A single instruction or a piece of data that doesn't decode to one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    /// The bytes of the line: 2 for most instructions, 4 for the
+    /// XO-CHIP F000 NNNN long load and 1 or 2 for data.
+    pub bytes: Vec<u8>,
+    /// The instruction the bytes decode to, if any.
+    pub instruction: Option<Instruction>,
+}
+
+/// Disassembles `code`, which is loaded at `base`.
+pub fn disassemble(code: &[u8], base: u16, variant: Variant) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < code.len() {
+        let address = base.wrapping_add(offset as u16);
+        let rest = &code[offset..];
+        let instruction = if rest.len() >= 2 {
+            Instruction::decode((rest[0] as u16) << 8 | rest[1] as u16).ok()
+                .filter(|instruction| instruction.variant() <= variant && rest.len() >= instruction.size() as usize)
+        } else {
+            None
+        };
+        let len = instruction.map_or(rest.len().min(2), |instruction| instruction.size() as usize);
+        let line = Line { address, bytes: rest[..len].to_vec(), instruction };
+
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// The addresses that are jumped to or called and at which one of
+/// `lines` starts, so they can be labelled.
+pub fn branch_targets(lines: &[Line]) -> BTreeSet<u16> {
+    let starts: BTreeSet<u16> = lines.iter().map(|line| line.address).collect();
+    lines.iter()
+        .filter_map(|line| line.instruction.and_then(|instruction| instruction.target()))
+        .filter(|target| starts.contains(target))
+        .collect()
+}
+
+/// The name of the label at `address`.
+pub fn label(address: u16) -> String {
+    format!("L{:03X}", address)
+}
+
+fn hex(value: u16, digits: usize, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Classic => format!("#{:0width$X}", value, width = digits),
+        Syntax::Octo => format!("0x{:0width$X}", value, width = digits),
+    }
+}
+
+impl Line {
+    /// The first word of the line, or its only byte.
+    pub fn opcode(&self) -> u16 {
+        self.bytes.iter().take(2).fold(0, |word, byte| word << 8 | *byte as u16)
+    }
+
+    /// Formats the line in `syntax`, referring to the addresses in
+    /// `labels` by their label.
+    pub fn format(&self, syntax: Syntax, labels: &BTreeSet<u16>) -> String {
+        let instruction = match self.instruction {
+            Some(instruction) => instruction,
+            None => {
+                let bytes: Vec<String> = self.bytes.iter().map(|byte| hex(*byte as u16, 2, syntax)).collect();
+                return match syntax {
+                    Syntax::Classic => format!("DB {}", bytes.join(", ")),
+                    Syntax::Octo => bytes.join(" "),
+                };
+            },
+        };
+
+        let register = |r: u8| match syntax {
+            Syntax::Classic => format!("V{:X}", r),
+            Syntax::Octo => format!("v{:x}", r),
+        };
+        let byte = |nn: u8| hex(nn as u16, 2, syntax);
+        let address = |nnn: u16, digits: usize| if labels.contains(&nnn) { label(nnn) } else { hex(nnn, digits, syntax) };
+
+        use instruction::Instruction::*;
+        let (classic, octo) = match instruction {
+            Cls => ("CLS".to_string(), "clear".to_string()),
+            Ret => ("RET".to_string(), "return".to_string()),
+            ScrollRight => ("SCR".to_string(), "scroll-right".to_string()),
+            ScrollLeft => ("SCL".to_string(), "scroll-left".to_string()),
+            Exit => ("EXIT".to_string(), "exit".to_string()),
+            Low => ("LOW".to_string(), "lores".to_string()),
+            High => ("HIGH".to_string(), "hires".to_string()),
+            ScrollDown(n) => (format!("SCD {}", n), format!("scroll-down {}", n)),
+            ScrollUp(n) => (format!("SCU {}", n), format!("scroll-up {}", n)),
+            Jp(nnn) => (format!("JP {}", address(nnn, 3)), format!("jump {}", address(nnn, 3))),
+            Call(nnn) => (
+                format!("CALL {}", address(nnn, 3)),
+                if labels.contains(&nnn) { label(nnn) } else { format!(":call {}", address(nnn, 3)) },
+            ),
+            SeVxByte { x, nn } => (format!("SE {}, {}", register(x), byte(nn)), format!("if {} != {} then", register(x), byte(nn))),
+            SneVxByte { x, nn } => (format!("SNE {}, {}", register(x), byte(nn)), format!("if {} == {} then", register(x), byte(nn))),
+            SeVxVy { x, y } => (format!("SE {}, {}", register(x), register(y)), format!("if {} != {} then", register(x), register(y))),
+            SaveRange { x, y } => (format!("SAVE {} - {}", register(x), register(y)), format!("save {} - {}", register(x), register(y))),
+            LoadRange { x, y } => (format!("LOAD {} - {}", register(x), register(y)), format!("load {} - {}", register(x), register(y))),
+            LdVxByte { x, nn } => (format!("LD {}, {}", register(x), byte(nn)), format!("{} := {}", register(x), byte(nn))),
+            AddVxByte { x, nn } => (format!("ADD {}, {}", register(x), byte(nn)), format!("{} += {}", register(x), byte(nn))),
+            LdVxVy { x, y } | Or { x, y } | And { x, y } | Xor { x, y } | AddVxVy { x, y }
+            | Sub { x, y } | Shr { x, y } | Subn { x, y } | Shl { x, y } => {
+                let (mnemonic, operator) = match instruction {
+                    LdVxVy { .. } => ("LD", ":="),
+                    Or { .. } => ("OR", "|="),
+                    And { .. } => ("AND", "&="),
+                    Xor { .. } => ("XOR", "^="),
+                    AddVxVy { .. } => ("ADD", "+="),
+                    Sub { .. } => ("SUB", "-="),
+                    Shr { .. } => ("SHR", ">>="),
+                    Subn { .. } => ("SUBN", "=-"),
+                    _ => ("SHL", "<<="),
+                };
+                (format!("{} {}, {}", mnemonic, register(x), register(y)), format!("{} {} {}", register(x), operator, register(y)))
+            },
+            SneVxVy { x, y } => (format!("SNE {}, {}", register(x), register(y)), format!("if {} == {} then", register(x), register(y))),
+            LdI(nnn) => (format!("LD I, {}", address(nnn, 3)), format!("i := {}", address(nnn, 3))),
+            JpV0(nnn) => (format!("JP V0, {}", address(nnn, 3)), format!("jump0 {}", address(nnn, 3))),
+            Rnd { x, nn } => (format!("RND {}, {}", register(x), byte(nn)), format!("{} := random {}", register(x), byte(nn))),
+            Drw { x, y, n } => (
+                format!("DRW {}, {}, {}", register(x), register(y), n),
+                format!("sprite {} {} {}", register(x), register(y), n),
+            ),
+            Skp { x } => (format!("SKP {}", register(x)), format!("if {} -key then", register(x))),
+            Sknp { x } => (format!("SKNP {}", register(x)), format!("if {} key then", register(x))),
+            LdILong => {
+                let nnnn = address((self.bytes[2] as u16) << 8 | self.bytes[3] as u16, 4);
+                (format!("LD I, {}", nnnn), format!("i := long {}", nnnn))
+            },
+            Plane(n) => (format!("PLANE {}", n), format!("plane {}", n)),
+            Audio => ("AUDIO".to_string(), "audio".to_string()),
+            LdVxDt { x } => (format!("LD {}, DT", register(x)), format!("{} := delay", register(x))),
+            LdVxK { x } => (format!("LD {}, K", register(x)), format!("{} := key", register(x))),
+            LdDtVx { x } => (format!("LD DT, {}", register(x)), format!("delay := {}", register(x))),
+            LdStVx { x } => (format!("LD ST, {}", register(x)), format!("buzzer := {}", register(x))),
+            AddIVx { x } => (format!("ADD I, {}", register(x)), format!("i += {}", register(x))),
+            LdFVx { x } => (format!("LD F, {}", register(x)), format!("i := hex {}", register(x))),
+            LdHfVx { x } => (format!("LD HF, {}", register(x)), format!("i := bighex {}", register(x))),
+            LdBVx { x } => (format!("LD B, {}", register(x)), format!("bcd {}", register(x))),
+            Pitch { x } => (format!("PITCH {}", register(x)), format!("pitch := {}", register(x))),
+            LdIVx { x } => (format!("LD [I], {}", register(x)), format!("save {}", register(x))),
+            LdVxI { x } => (format!("LD {}, [I]", register(x)), format!("load {}", register(x))),
+            LdRVx { x } => (format!("LD R, {}", register(x)), format!("saveflags {}", register(x))),
+            LdVxR { x } => (format!("LD {}, R", register(x)), format!("loadflags {}", register(x))),
+        };
+
+        match syntax {
+            Syntax::Classic => classic,
+            Syntax::Octo => octo,
+        }
+    }
+}
+
+/// Disassembles the instruction at `address` in `memory` without
+/// labels, e.g. to show the instruction about to be executed. Returns
+/// `None` past the end of memory.
+pub fn instruction_at(memory: &[u8], address: u16, variant: Variant, syntax: Syntax) -> Option<String> {
+    let start = address as usize;
+    if start >= memory.len() {
+        return None;
+    }
+    let end = (start + 4).min(memory.len());
+    let line = disassemble(&memory[start..end], address, variant).remove(0);
+    Some(line.format(syntax, &BTreeSet::new()))
+}
+
+/// Disassembles `code` loaded at `base` into a listing with labels at
+/// branch targets. Octo listings only contain addresses as comments,
+/// so they can be assembled again.
+pub fn listing(code: &[u8], base: u16, variant: Variant, syntax: Syntax) -> String {
+    let lines = disassemble(code, base, variant);
+    let labels = branch_targets(&lines);
+    let mut out = String::new();
+
+    for line in lines.iter() {
+        if labels.contains(&line.address) {
+            match syntax {
+                Syntax::Classic => writeln!(out, "{}:", label(line.address)).unwrap(),
+                Syntax::Octo => writeln!(out, ": {}", label(line.address)).unwrap(),
+            }
+        }
+
+        let bytes: String = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        match syntax {
+            Syntax::Classic => writeln!(out, "    {:03X}  {:8}  {}", line.address, bytes, line.format(syntax, &labels)),
+            Syntax::Octo => writeln!(out, "    {:24}  # {:03X}  {}", line.format(syntax, &labels), line.address, bytes),
+        }.unwrap();
+    }
+
+    out
+}
+
+#[test]
+fn test_disassemble() {
+    let code = [0x60, 0x05, 0x22, 0x08, 0x12, 0x02, 0xFF, 0xFF, 0xD0, 0x15, 0x00, 0xEE, 0xAB];
+    let lines = disassemble(&code, 0x200, Variant::Chip8);
+
+    let addresses: Vec<u16> = lines.iter().map(|line| line.address).collect();
+    assert_eq!(addresses, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A, 0x20C]);
+    assert_eq!(lines[1].instruction, Some(Instruction::Call(0x208)));
+    assert_eq!(lines[2].instruction, Some(Instruction::Jp(0x202)));
+    assert_eq!(lines[3].instruction, None);
+    assert_eq!(lines[6].bytes, [0xAB]);
+    assert_eq!(branch_targets(&lines).into_iter().collect::<Vec<_>>(), [0x202, 0x208]);
+}
+
+#[test]
+fn test_disassemble_variants() {
+    let code = [0x00, 0xFF, 0xF0, 0x00, 0x12, 0x34, 0x51, 0x22];
+
+    let lines = disassemble(&code, 0x200, Variant::Chip8);
+    assert_eq!(lines.iter().filter(|line| line.instruction.is_some()).count(), 1);
+
+    let lines = disassemble(&code, 0x200, Variant::XoChip);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1].bytes, [0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(lines[1].format(Syntax::Octo, &BTreeSet::new()), "i := long 0x1234");
+    assert_eq!(lines[2].format(Syntax::Classic, &BTreeSet::new()), "SAVE V1 - V2");
+}
+
+#[test]
+fn test_format() {
+    let cases: [(u16, &str, &str); 12] = [
+        (0x00E0, "CLS", "clear"),
+        (0x00C4, "SCD 4", "scroll-down 4"),
+        (0x3A2F, "SE VA, #2F", "if va != 0x2F then"),
+        (0x6123, "LD V1, #23", "v1 := 0x23"),
+        (0x8AB7, "SUBN VA, VB", "va =- vb"),
+        (0x8AB6, "SHR VA, VB", "va >>= vb"),
+        (0xA2F0, "LD I, #2F0", "i := 0x2F0"),
+        (0xB300, "JP V0, #300", "jump0 0x300"),
+        (0xD125, "DRW V1, V2, 5", "sprite v1 v2 5"),
+        (0xE39E, "SKP V3", "if v3 -key then"),
+        (0xF50A, "LD V5, K", "v5 := key"),
+        (0xF265, "LD V2, [I]", "load v2"),
+    ];
+
+    for &(opcode, classic, octo) in cases.iter() {
+        let lines = disassemble(&[(opcode >> 8) as u8, opcode as u8], 0x200, Variant::XoChip);
+        assert_eq!(lines[0].format(Syntax::Classic, &BTreeSet::new()), classic);
+        assert_eq!(lines[0].format(Syntax::Octo, &BTreeSet::new()), octo);
+    }
+}
+
+#[test]
+fn test_listing() {
+    // A loop calling a subroutine outside of the code, which can't
+    // be labelled.
+    let code = [0x22, 0x06, 0x12, 0x00, 0x00, 0xEE];
+    assert_eq!(listing(&code, 0x200, Variant::Chip8, Syntax::Classic), "\
+L200:
+    200  2206      CALL #206
+    202  1200      JP L200
+    204  00EE      RET
+");
+
+    let code = [0x22, 0x04, 0x12, 0x00, 0x00, 0xEE];
+    assert_eq!(listing(&code, 0x200, Variant::Chip8, Syntax::Octo), "\
+: L200
+    L204                      # 200  2204
+    jump L200                 # 202  1200
+: L204
+    return                    # 204  00EE
+");
+}
@@ -0,0 +1,201 @@
+use std::slice::Chunks;
+
+pub(crate) const LORES_WIDTH: usize = 64;
+pub(crate) const LORES_HEIGHT: usize = 32;
+pub(crate) const HIRES_WIDTH: usize = 128;
+pub(crate) const HIRES_HEIGHT: usize = 64;
+
+/// A rectangle of pixels on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    /// The smallest region containing both `self` and the pixel at
+    /// `x`, `y`.
+    fn including(self, x: usize, y: usize) -> Region {
+        let (left, top) = (self.x.min(x), self.y.min(y));
+        let right = (self.x + self.width).max(x + 1);
+        let bottom = (self.y + self.height).max(y + 1);
+        Region { x: left, y: top, width: right - left, height: bottom - top }
+    }
+}
+
+/// The screen: 64x32 pixels, or 128x64 in SUPER-CHIP hi-res mode.
+///
+/// Each pixel is a bitmask of the XO-CHIP planes it is set in, so it is
+/// always 0 or 1 outside of XO-CHIP and 0 to 3 on XO-CHIP.
+///
+/// The display keeps track of the region that changed since it was
+/// last asked with `take_dirty`, so that a frontend only needs to draw
+/// when something changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Display {
+    width: usize,
+    height: usize,
+    /// The pixels row by row.
+    pixels: Vec<u8>,
+    dirty: Option<Region>,
+}
+
+impl Display {
+    /// A blank screen in the low or high resolution mode. All of it
+    /// counts as changed.
+    pub(crate) fn new(hires: bool) -> Display {
+        let (width, height) = if hires { (HIRES_WIDTH, HIRES_HEIGHT) } else { (LORES_WIDTH, LORES_HEIGHT) };
+        Display {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            dirty: Some(Region { x: 0, y: 0, width, height }),
+        }
+    }
+
+    /// Width of the screen in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the screen in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH
+    }
+
+    /// The planes the pixel at `x`, `y` is set in, 0 if it is unset.
+    ///
+    /// # Panics
+    ///
+    /// If the pixel lies outside of the screen.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        assert!(x < self.width && y < self.height, "pixel {},{} is outside of the screen", x, y);
+        self.pixels[y * self.width + x]
+    }
+
+    /// The pixels of row `y`, left to right.
+    pub fn row(&self, y: usize) -> &[u8] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    /// The rows from top to bottom.
+    pub fn rows(&self) -> Chunks<'_, u8> {
+        self.pixels.chunks(self.width)
+    }
+
+    /// Whether anything changed since the last call to `take_dirty`.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// Returns the region that changed since the last call, if any, and
+    /// starts tracking changes anew.
+    pub fn take_dirty(&mut self) -> Option<Region> {
+        self.dirty.take()
+    }
+
+    /// All pixels row by row.
+    pub(crate) fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// All pixels row by row, for changes that mark the whole screen
+    /// dirty.
+    pub(crate) fn pixels_mut(&mut self) -> &mut [u8] {
+        self.mark_all();
+        &mut self.pixels
+    }
+
+    fn mark(&mut self, x: usize, y: usize) {
+        self.dirty = Some(match self.dirty {
+            Some(region) => region.including(x, y),
+            None => Region { x, y, width: 1, height: 1 },
+        });
+    }
+
+    fn mark_all(&mut self) {
+        self.dirty = Some(Region { x: 0, y: 0, width: self.width, height: self.height });
+    }
+
+    /// Unsets `planes` in all pixels.
+    pub(crate) fn clear(&mut self, planes: u8) {
+        if self.pixels.iter().any(|pixel| pixel & planes != 0) {
+            for pixel in self.pixels.iter_mut() {
+                *pixel &= !planes;
+            }
+            self.mark_all();
+        }
+    }
+
+    /// Flips `plane` of the pixel at `x`, `y` and returns whether it
+    /// was set before.
+    pub(crate) fn flip(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let pixel = &mut self.pixels[y * self.width + x];
+        let was_set = *pixel & plane != 0;
+        *pixel ^= plane;
+        self.mark(x, y);
+        was_set
+    }
+
+    /// Moves the content of `planes` by `dx` pixels to the right and
+    /// `dy` pixels down. Pixels moved in from outside the screen are
+    /// unset.
+    pub(crate) fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let width = self.width as isize;
+        let height = self.height as isize;
+        let mut pixels: Vec<u8> = self.pixels.iter().map(|pixel| pixel & !planes).collect();
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                if from_x >= 0 && from_x < width && from_y >= 0 && from_y < height {
+                    pixels[(y * width + x) as usize] |= self.pixels[(from_y * width + from_x) as usize] & planes;
+                }
+            }
+        }
+        self.pixels = pixels;
+        self.mark_all();
+    }
+}
+
+#[test]
+fn test_dirty() {
+    let mut display = Display::new(false);
+    assert_eq!(display.take_dirty(), Some(Region { x: 0, y: 0, width: 64, height: 32 }));
+    assert_eq!(display.take_dirty(), None);
+
+    assert!(!display.flip(10, 5, 1));
+    assert!(display.flip(10, 5, 1));
+    display.flip(3, 20, 1);
+    assert!(display.is_dirty());
+    assert_eq!(display.take_dirty(), Some(Region { x: 3, y: 5, width: 8, height: 16 }));
+
+    // Clearing a blank screen changes nothing.
+    display.flip(3, 20, 1);
+    display.take_dirty();
+    display.clear(1);
+    assert_eq!(display.take_dirty(), None);
+    display.flip(0, 0, 2);
+    display.clear(1);
+    assert_eq!(display.take_dirty(), Some(Region { x: 0, y: 0, width: 1, height: 1 }));
+    display.clear(2);
+    assert_eq!(display.take_dirty(), Some(Region { x: 0, y: 0, width: 64, height: 32 }));
+}
+
+#[test]
+fn test_rows() {
+    let mut display = Display::new(true);
+    display.flip(127, 1, 1);
+    display.flip(0, 63, 2);
+    assert_eq!((display.width(), display.height()), (128, 64));
+    assert_eq!(display.rows().len(), 64);
+    assert_eq!(display.rows().nth(1).unwrap()[127], 1);
+    assert_eq!(display.row(63)[0], 2);
+    assert_eq!(display.pixel(127, 1), 1);
+    assert_eq!(display.pixel(1, 1), 0);
+}
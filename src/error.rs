@@ -0,0 +1,195 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errors that abort the execution of a single instruction.
+///
+/// When `Chip8::cycle` returns one of these, the machine state is left
+/// exactly as it was before the faulting instruction, so a host can
+/// inspect it, show a diagnostic and decide how to continue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The word at `pc` does not decode to a supported instruction.
+    InvalidOpcode { pc: u16, opcode: u16 },
+    /// 2NNN was executed with all 16 stack entries in use.
+    StackOverflow { pc: u16 },
+    /// 00EE was executed with an empty stack.
+    StackUnderflow { pc: u16 },
+    /// The instruction at `pc` tried to access `address`, which lies
+    /// outside of memory. XO-CHIP memory wraps around instead.
+    MemoryFault { pc: u16, address: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::InvalidOpcode { pc, opcode } =>
+                write!(f, "invalid opcode {:04X} at {:03X}", opcode, pc),
+            Chip8Error::StackOverflow { pc } =>
+                write!(f, "stack overflow at {:03X}", pc),
+            Chip8Error::StackUnderflow { pc } =>
+                write!(f, "stack underflow at {:03X}", pc),
+            Chip8Error::MemoryFault { pc, address } =>
+                write!(f, "memory access to {:04X} out of range at {:03X}", address, pc),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+/// Returned by `Instruction::decode` for a word that isn't an
+/// instruction on any variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X} is not an instruction", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Errors returned when loading a ROM into memory. Memory is left
+/// unchanged when one occurs.
+#[derive(Debug)]
+pub enum LoadError {
+    /// Reading the ROM failed.
+    Io(io::Error),
+    /// The ROM is `size` bytes long, but only `available` bytes of
+    /// memory follow the load address.
+    TooBig { size: usize, available: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref err) => write!(f, "{}", err),
+            LoadError::TooBig { size, available } =>
+                write!(f, "ROM is {} bytes, but only {} fit in memory", size, available),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+/// Errors returned by `Chip8::load_state` for data that is not a valid
+/// save state. The machine is left unchanged when one occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state magic bytes.
+    InvalidMagic,
+    /// The save state was written by a newer, unknown format version.
+    UnsupportedVersion(u16),
+    /// The data ends before the save state does.
+    Truncated,
+    /// The data fails the checksum or contains a value that no
+    /// machine can be in.
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::InvalidMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(f, "unsupported save state version {}", version),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// Errors returned when reading or replaying a `Movie`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data is not a valid movie file, for the same reasons a save
+    /// state can be invalid.
+    Format(StateError),
+    /// The movie was recorded with a different ROM.
+    RomMismatch,
+    /// The ROM doesn't fit in memory, see `LoadError::TooBig`.
+    RomTooBig { size: usize, available: usize },
+    /// The screen after `frame` frames differs from the one recorded,
+    /// so the replay has gone out of sync.
+    Desync { frame: u32, expected: u64, actual: u64 },
+    /// An instruction failed during the replay.
+    Emulation(Chip8Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MovieError::Format(StateError::InvalidMagic) => write!(f, "not a movie"),
+            MovieError::Format(StateError::UnsupportedVersion(version)) =>
+                write!(f, "unsupported movie version {}", version),
+            MovieError::Format(StateError::Truncated) => write!(f, "movie is truncated"),
+            MovieError::Format(StateError::Corrupt) => write!(f, "movie is corrupt"),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different ROM"),
+            MovieError::RomTooBig { size, available } => write!(f, "{}", LoadError::TooBig { size, available }),
+            MovieError::Desync { frame, expected, actual } =>
+                write!(f, "screen hash {:016X} differs from recorded {:016X} at frame {}", actual, expected, frame),
+            MovieError::Emulation(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(err: StateError) -> MovieError {
+        MovieError::Format(err)
+    }
+}
+
+impl From<Chip8Error> for MovieError {
+    fn from(err: Chip8Error) -> MovieError {
+        MovieError::Emulation(err)
+    }
+}
+
+/// An error in assembly source, at a 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Errors in a ROM database file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// The file is not valid JSON, at a 1-based line and column.
+    Syntax { line: usize, column: usize, message: &'static str },
+    /// The JSON is valid, but a program or ROM entry doesn't have the
+    /// layout of the database.
+    Format(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DatabaseError::Syntax { line, column, message } => write!(f, "{}:{}: {}", line, column, message),
+            DatabaseError::Format(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for DatabaseError {}
@@ -0,0 +1,326 @@
+//! Decoding and encoding of single instructions.
+//!
+//! The variants are named after the mnemonics of Cowgod's Chip-8
+//! technical reference, extended to SUPER-CHIP and XO-CHIP. Decoding
+//! accepts every instruction of every variant; `Instruction::variant`
+//! tells which platforms actually support it.
+
+use error::DecodeError;
+use quirks::Variant;
+
+/// A decoded instruction. Registers are given by their index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// 00CN - Scrolls the display down by N pixels. (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00DN - Scrolls the display up by N pixels. (XO-CHIP)
+    ScrollUp(u8),
+    /// 00E0 - Clears the screen.
+    Cls,
+    /// 00EE - Returns from a subroutine.
+    Ret,
+    /// 00FB - Scrolls the display right by 4 pixels. (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC - Scrolls the display left by 4 pixels. (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD - Exits the interpreter. (SUPER-CHIP)
+    Exit,
+    /// 00FE - Switches to the low resolution mode. (SUPER-CHIP)
+    Low,
+    /// 00FF - Switches to the high resolution mode. (SUPER-CHIP)
+    High,
+    /// 1NNN - Jumps to NNN.
+    Jp(u16),
+    /// 2NNN - Calls the subroutine at NNN.
+    Call(u16),
+    /// 3XNN - Skips the next instruction if VX equals NN.
+    SeVxByte { x: u8, nn: u8 },
+    /// 4XNN - Skips the next instruction if VX doesn't equal NN.
+    SneVxByte { x: u8, nn: u8 },
+    /// 5XY0 - Skips the next instruction if VX equals VY.
+    SeVxVy { x: u8, y: u8 },
+    /// 5XY2 - Stores VX to VY at I. (XO-CHIP)
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3 - Loads VX to VY from I. (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN - Sets VX to NN.
+    LdVxByte { x: u8, nn: u8 },
+    /// 7XNN - Adds NN to VX.
+    AddVxByte { x: u8, nn: u8 },
+    /// 8XY0 - Sets VX to VY.
+    LdVxVy { x: u8, y: u8 },
+    /// 8XY1 - Sets VX to VX or VY.
+    Or { x: u8, y: u8 },
+    /// 8XY2 - Sets VX to VX and VY.
+    And { x: u8, y: u8 },
+    /// 8XY3 - Sets VX to VX xor VY.
+    Xor { x: u8, y: u8 },
+    /// 8XY4 - Adds VY to VX with carry.
+    AddVxVy { x: u8, y: u8 },
+    /// 8XY5 - Subtracts VY from VX with borrow.
+    Sub { x: u8, y: u8 },
+    /// 8XY6 - Shifts right.
+    Shr { x: u8, y: u8 },
+    /// 8XY7 - Sets VX to VY minus VX with borrow.
+    Subn { x: u8, y: u8 },
+    /// 8XYE - Shifts left.
+    Shl { x: u8, y: u8 },
+    /// 9XY0 - Skips the next instruction if VX doesn't equal VY.
+    SneVxVy { x: u8, y: u8 },
+    /// ANNN - Sets I to NNN.
+    LdI(u16),
+    /// BNNN - Jumps to NNN plus V0, or to XNN plus VX with the jump
+    /// quirk.
+    JpV0(u16),
+    /// CXNN - Sets VX to a random number and NN.
+    Rnd { x: u8, nn: u8 },
+    /// DXYN - Draws a sprite N rows high at VX, VY.
+    Drw { x: u8, y: u8, n: u8 },
+    /// EX9E - Skips the next instruction if the key in VX is pressed.
+    Skp { x: u8 },
+    /// EXA1 - Skips the next instruction if the key in VX isn't
+    /// pressed.
+    Sknp { x: u8 },
+    /// F000 NNNN - Sets I to the address in the following word.
+    /// (XO-CHIP)
+    LdILong,
+    /// FN01 - Selects the drawing planes. (XO-CHIP)
+    Plane(u8),
+    /// F002 - Loads the audio pattern from I. (XO-CHIP)
+    Audio,
+    /// FX07 - Sets VX to the delay timer.
+    LdVxDt { x: u8 },
+    /// FX0A - Waits for a key and stores it in VX.
+    LdVxK { x: u8 },
+    /// FX15 - Sets the delay timer to VX.
+    LdDtVx { x: u8 },
+    /// FX18 - Sets the sound timer to VX.
+    LdStVx { x: u8 },
+    /// FX1E - Adds VX to I.
+    AddIVx { x: u8 },
+    /// FX29 - Sets I to the small font character in VX.
+    LdFVx { x: u8 },
+    /// FX30 - Sets I to the big font character in VX. (SUPER-CHIP)
+    LdHfVx { x: u8 },
+    /// FX33 - Stores the decimal digits of VX at I.
+    LdBVx { x: u8 },
+    /// FX3A - Sets the audio pitch to VX. (XO-CHIP)
+    Pitch { x: u8 },
+    /// FX55 - Stores V0 to VX at I.
+    LdIVx { x: u8 },
+    /// FX65 - Loads V0 to VX from I.
+    LdVxI { x: u8 },
+    /// FX75 - Stores V0 to VX in the RPL user flags. (SUPER-CHIP)
+    LdRVx { x: u8 },
+    /// FX85 - Loads V0 to VX from the RPL user flags. (SUPER-CHIP)
+    LdVxR { x: u8 },
+}
+
+use self::Instruction::*;
+
+impl Instruction {
+    /// Decodes `opcode`, failing if no variant has an instruction
+    /// with that encoding.
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00E0 => Cls,
+                0x00EE => Ret,
+                0x00FB => ScrollRight,
+                0x00FC => ScrollLeft,
+                0x00FD => Exit,
+                0x00FE => Low,
+                0x00FF => High,
+                _ if opcode & 0xFFF0 == 0x00C0 => ScrollDown(n),
+                _ if opcode & 0xFFF0 == 0x00D0 => ScrollUp(n),
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x1000 => Jp(nnn),
+            0x2000 => Call(nnn),
+            0x3000 => SeVxByte { x, nn },
+            0x4000 => SneVxByte { x, nn },
+            0x5000 => match n {
+                0 => SeVxVy { x, y },
+                2 => SaveRange { x, y },
+                3 => LoadRange { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x6000 => LdVxByte { x, nn },
+            0x7000 => AddVxByte { x, nn },
+            0x8000 => match n {
+                0 => LdVxVy { x, y },
+                1 => Or { x, y },
+                2 => And { x, y },
+                3 => Xor { x, y },
+                4 => AddVxVy { x, y },
+                5 => Sub { x, y },
+                6 => Shr { x, y },
+                7 => Subn { x, y },
+                0xE => Shl { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x9000 if n == 0 => SneVxVy { x, y },
+            0xA000 => LdI(nnn),
+            0xB000 => JpV0(nnn),
+            0xC000 => Rnd { x, nn },
+            0xD000 => Drw { x, y, n },
+            0xE000 => match nn {
+                0x9E => Skp { x },
+                0xA1 => Sknp { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0xF000 => match nn {
+                0x00 if x == 0 => LdILong,
+                0x01 => Plane(x),
+                0x02 if x == 0 => Audio,
+                0x07 => LdVxDt { x },
+                0x0A => LdVxK { x },
+                0x15 => LdDtVx { x },
+                0x18 => LdStVx { x },
+                0x1E => AddIVx { x },
+                0x29 => LdFVx { x },
+                0x30 => LdHfVx { x },
+                0x33 => LdBVx { x },
+                0x3A => Pitch { x },
+                0x55 => LdIVx { x },
+                0x65 => LdVxI { x },
+                0x75 => LdRVx { x },
+                0x85 => LdVxR { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            _ => return Err(DecodeError { opcode }),
+        };
+        Ok(instruction)
+    }
+
+    /// The opcode of the instruction. For `LdILong` that is only the
+    /// first of its two words.
+    pub fn encode(&self) -> u16 {
+        let xy = |opcode: u16, x: u8, y: u8| opcode | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |opcode: u16, x: u8, nn: u8| opcode | (x as u16) << 8 | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16) << 8 | nn;
+
+        match *self {
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Low => 0x00FE,
+            High => 0x00FF,
+            Jp(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SeVxByte { x, nn } => xnn(0x3000, x, nn),
+            SneVxByte { x, nn } => xnn(0x4000, x, nn),
+            SeVxVy { x, y } => xy(0x5000, x, y),
+            SaveRange { x, y } => xy(0x5002, x, y),
+            LoadRange { x, y } => xy(0x5003, x, y),
+            LdVxByte { x, nn } => xnn(0x6000, x, nn),
+            AddVxByte { x, nn } => xnn(0x7000, x, nn),
+            LdVxVy { x, y } => xy(0x8000, x, y),
+            Or { x, y } => xy(0x8001, x, y),
+            And { x, y } => xy(0x8002, x, y),
+            Xor { x, y } => xy(0x8003, x, y),
+            AddVxVy { x, y } => xy(0x8004, x, y),
+            Sub { x, y } => xy(0x8005, x, y),
+            Shr { x, y } => xy(0x8006, x, y),
+            Subn { x, y } => xy(0x8007, x, y),
+            Shl { x, y } => xy(0x800E, x, y),
+            SneVxVy { x, y } => xy(0x9000, x, y),
+            LdI(nnn) => 0xA000 | nnn,
+            JpV0(nnn) => 0xB000 | nnn,
+            Rnd { x, nn } => xnn(0xC000, x, nn),
+            Drw { x, y, n } => xy(0xD000, x, y) | n as u16,
+            Skp { x } => xnn(0xE000, x, 0x9E),
+            Sknp { x } => xnn(0xE000, x, 0xA1),
+            LdILong => 0xF000,
+            Plane(n) => fx(n, 0x01),
+            Audio => 0xF002,
+            LdVxDt { x } => fx(x, 0x07),
+            LdVxK { x } => fx(x, 0x0A),
+            LdDtVx { x } => fx(x, 0x15),
+            LdStVx { x } => fx(x, 0x18),
+            AddIVx { x } => fx(x, 0x1E),
+            LdFVx { x } => fx(x, 0x29),
+            LdHfVx { x } => fx(x, 0x30),
+            LdBVx { x } => fx(x, 0x33),
+            Pitch { x } => fx(x, 0x3A),
+            LdIVx { x } => fx(x, 0x55),
+            LdVxI { x } => fx(x, 0x65),
+            LdRVx { x } => fx(x, 0x75),
+            LdVxR { x } => fx(x, 0x85),
+        }
+    }
+
+    /// The first variant that supports the instruction.
+    pub fn variant(&self) -> Variant {
+        match *self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Low | High
+            | LdHfVx { .. } | LdRVx { .. } | LdVxR { .. } => Variant::SuperChip,
+            ScrollUp(_) | SaveRange { .. } | LoadRange { .. }
+            | LdILong | Plane(_) | Audio | Pitch { .. } => Variant::XoChip,
+            _ => Variant::Chip8,
+        }
+    }
+
+    /// The number of bytes the instruction takes up in memory, 4 for
+    /// `LdILong` and 2 for all others.
+    pub fn size(&self) -> u16 {
+        if *self == LdILong { 4 } else { 2 }
+    }
+
+    /// The address jumped to or called, not counting the register
+    /// added by `JpV0`.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Jp(nnn) | Call(nnn) | JpV0(nnn) => Some(nnn),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(Instruction::decode(0x00E0), Ok(Cls));
+    assert_eq!(Instruction::decode(0x00C7), Ok(ScrollDown(7)));
+    assert_eq!(Instruction::decode(0x1ABC), Ok(Jp(0xABC)));
+    assert_eq!(Instruction::decode(0x3A2F), Ok(SeVxByte { x: 0xA, nn: 0x2F }));
+    assert_eq!(Instruction::decode(0x8AB7), Ok(Subn { x: 0xA, y: 0xB }));
+    assert_eq!(Instruction::decode(0xD125), Ok(Drw { x: 1, y: 2, n: 5 }));
+    assert_eq!(Instruction::decode(0xE3A1), Ok(Sknp { x: 3 }));
+    assert_eq!(Instruction::decode(0xF301), Ok(Plane(3)));
+    assert_eq!(Instruction::decode(0xF265), Ok(LdVxI { x: 2 }));
+
+    for &opcode in [0x0123, 0x5121, 0x8128, 0x9121, 0xE3A2, 0xF100, 0xF102, 0xF2FF].iter() {
+        assert_eq!(Instruction::decode(opcode), Err(DecodeError { opcode }));
+    }
+
+    assert_eq!(Cls.variant(), Variant::Chip8);
+    assert_eq!(High.variant(), Variant::SuperChip);
+    assert_eq!(SaveRange { x: 1, y: 2 }.variant(), Variant::XoChip);
+    assert_eq!(LdILong.size(), 4);
+    assert_eq!(Call(0x300).target(), Some(0x300));
+}
+
+#[test]
+fn test_decode_encode_round_trip() {
+    let mut valid = 0;
+    for opcode in 0..=0xFFFF {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+            valid += 1;
+        }
+    }
+    // 39 instructions starting with 0, 4096 each for the ten with a
+    // 12 bit operand, 768 for 5, 2304 for 8, 256 for 9, 32 for E and
+    // 226 for F.
+    assert_eq!(valid, 44585);
+}
@@ -0,0 +1,264 @@
+//! Just enough of a JSON parser to read the ROM database.
+
+use error::DatabaseError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they appear in the file.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses `text`, which has to hold exactly one value.
+    pub(crate) fn parse(text: &str) -> Result<Json, DatabaseError> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("unexpected data after the value"));
+        }
+        Ok(value)
+    }
+
+    /// The member `key` of an object.
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        self.as_object()?.iter().find(|member| member.0 == key).map(|member| &member.1)
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_object(&self) -> Option<&[(String, Json)]> {
+        match *self {
+            Json::Object(ref members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of the next character.
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// An error at the current position.
+    fn error(&self, message: &'static str) -> DatabaseError {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rfind('\n').map_or(before, |newline| &before[newline + 1..]).chars().count() + 1;
+        DatabaseError::Syntax { line, column, message }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes `token` if the text continues with it.
+    fn eat(&mut self, token: &str) -> bool {
+        if self.text[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, DatabaseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ if self.eat("null") => Ok(Json::Null),
+            _ if self.eat("true") => Ok(Json::Bool(true)),
+            _ if self.eat("false") => Ok(Json::Bool(false)),
+            None => Err(self.error("unexpected end of file")),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, DatabaseError> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(":") {
+                return Err(self.error("expected ':'"));
+            }
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(Json::Object(members));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, DatabaseError> {
+        self.pos += 1;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(Json::Array(values));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, DatabaseError> {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') | Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E') = self.peek() {
+            self.pos += 1;
+        }
+        match self.text[start..self.pos].parse() {
+            Ok(number) => Ok(Json::Number(number)),
+            Err(_) => {
+                self.pos = start;
+                Err(self.error("invalid number"))
+            },
+        }
+    }
+
+    /// Four hex digits of a `\u` escape.
+    fn hex4(&mut self) -> Result<u32, DatabaseError> {
+        let digits = self.text.get(self.pos..self.pos + 4)
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, DatabaseError> {
+        self.pos += 1;
+        let mut string = String::new();
+        let mut start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    string.push_str(&self.text[start..self.pos]);
+                    self.pos += 1;
+                    return Ok(string);
+                },
+                Some(b'\\') => {
+                    string.push_str(&self.text[start..self.pos]);
+                    self.pos += 1;
+                    let escape = self.peek().ok_or_else(|| self.error("unexpected end of file"))?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // Characters outside the BMP are written as
+                            // a UTF-16 surrogate pair.
+                            if (0xD800..0xDC00).contains(&code) && self.eat("\\u") {
+                                let low = self.hex4()?;
+                                if (0xDC00..0xE000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                                }
+                            }
+                            std::char::from_u32(code).unwrap_or('\u{FFFD}')
+                        },
+                        _ => {
+                            self.pos -= 2;
+                            return Err(self.error("invalid escape"));
+                        },
+                    };
+                    string.push(c);
+                    start = self.pos;
+                },
+                Some(0..=0x1F) => return Err(self.error("control character in string")),
+                Some(_) => self.pos += 1,
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse() {
+    let json = Json::parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "x\"é\u00e9\ud83d\ude00"}, "d": {}} "#).unwrap();
+    assert_eq!(json.get("a"), Some(&Json::Array(vec![
+        Json::Number(1.0), Json::Number(-25.0), Json::Bool(true), Json::Null,
+    ])));
+    assert_eq!(json.get("b").and_then(|b| b.get("c")).and_then(Json::as_str), Some("x\"éé😀"));
+    assert_eq!(json.get("d").and_then(Json::as_object).map(|d| d.len()), Some(0));
+    assert_eq!(json.get("e"), None);
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = |text: &str| match Json::parse(text) {
+        Err(DatabaseError::Syntax { line, column, message }) => (line, column, message),
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(error("[1,\n 2,\n  x]"), (3, 3, "expected a value"));
+    assert_eq!(error("{\"a\" 1}"), (1, 6, "expected ':'"));
+    assert_eq!(error("\"abc"), (1, 5, "unterminated string"));
+    assert_eq!(error("[1] 2"), (1, 5, "unexpected data after the value"));
+    assert_eq!(error("[--1]"), (1, 2, "invalid number"));
+}
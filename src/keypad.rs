@@ -0,0 +1,51 @@
+/// The 16 keys of the hexadecimal CHIP-8 keypad, laid out as
+///
+/// ```text
+/// 1 2 3 C
+/// 4 5 6 D
+/// 7 8 9 E
+/// A 0 B F
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Num0 = 0x0,
+    Num1 = 0x1,
+    Num2 = 0x2,
+    Num3 = 0x3,
+    Num4 = 0x4,
+    Num5 = 0x5,
+    Num6 = 0x6,
+    Num7 = 0x7,
+    Num8 = 0x8,
+    Num9 = 0x9,
+    A = 0xA,
+    B = 0xB,
+    C = 0xC,
+    D = 0xD,
+    E = 0xE,
+    F = 0xF,
+}
+
+const KEYS: [Key; 16] = [
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3,
+    Key::Num4, Key::Num5, Key::Num6, Key::Num7,
+    Key::Num8, Key::Num9, Key::A, Key::B,
+    Key::C, Key::D, Key::E, Key::F,
+];
+
+impl Key {
+    /// Returns the key with the given hex value, if it is below 16.
+    pub fn from_index(index: u8) -> Option<Key> {
+        KEYS.get(index as usize).cloned()
+    }
+
+    /// The hex value of the key, as stored in VX by FX0A.
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// All keys ordered by their hex value.
+    pub fn all() -> &'static [Key; 16] {
+        &KEYS
+    }
+}
@@ -1,5 +1,41 @@
 #![allow(non_snake_case)]
 
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub mod asm;
+mod audio;
+pub mod disasm;
+mod display;
+mod error;
+mod instruction;
+mod json;
+mod keypad;
+mod movie;
+mod quirks;
+mod rewind;
+mod rng;
+mod romdb;
+mod sha1;
+mod state;
+pub mod terminal;
+mod trace;
+
+pub use audio::{AudioSink, SquareWave};
+pub use display::{Display, Region};
+pub use error::{AsmError, Chip8Error, DatabaseError, DecodeError, LoadError, MovieError, StateError};
+pub use instruction::Instruction;
+pub use keypad::Key;
+pub use movie::{Checkpoint, Movie, MOVIE_VERSION};
+pub use quirks::{LoadStoreQuirk, Quirks, Variant};
+use rewind::History;
+pub use rng::{Rng, Xorshift};
+pub use romdb::{RomDatabase, RomInfo};
+pub use state::STATE_VERSION;
+pub use trace::Trace;
+
 const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -19,17 +55,106 @@ const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// The 8x10 font used by FX30 on SUPER-CHIP. Stored in memory right
+/// after `FONT_SET`.
+const BIG_FONT_SET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+const FONT_ADDRESS: usize = 0;
+const BIG_FONT_ADDRESS: usize = FONT_ADDRESS + 80;
+
+/// Rate at which the delay and sound timers count down, in Hz.
+pub const TIMER_FREQUENCY: u32 = 60;
+/// Default number of instructions executed per second.
+pub const DEFAULT_CLOCK_SPEED: u32 = 700;
+/// Highest number of instructions executed per second, which keeps
+/// the budget of `step_for` from overflowing.
+pub const MAX_CLOCK_SPEED: u32 = 100_000_000;
+
+/// Where ROMs are loaded and execution starts on most platforms.
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+/// Where ROMs are loaded and execution starts on the ETI-660.
+pub const ETI660_LOAD_ADDRESS: u16 = 0x600;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+const MEMORY_SIZE: usize = 0x1000;
+const XOCHIP_MEMORY_SIZE: usize = 0x10000;
+
+/// Seeds the random number generator from the system clock.
+fn initial_seed() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0)
+}
+
+/// Pitch value at which XO-CHIP plays the audio pattern at 4000Hz.
+const DEFAULT_PITCH: u8 = 64;
+
+/// Registers X to Y for 5XY2 and 5XY3, in descending order if X is
+/// greater than Y.
+fn register_range(x: usize, y: usize) -> Vec<usize> {
+    if x <= y {
+        (x..y + 1).collect()
+    } else {
+        (y..x + 1).rev().collect()
+    }
+}
+
+/// Progress of FX0A, which completes once a key is pressed and
+/// released again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    Press,
+    Release(u8),
+}
+
+/// What happened during a successful call to `Chip8::cycle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Executed,
+    /// FX0A is blocking until a key is pressed. The program counter
+    /// did not advance.
+    WaitingForKey,
+    /// Execution is paused until the next call to `tick_timers`
+    /// because of the display wait quirk.
+    WaitingForVblank,
+    /// The program has exited through 00FD. Further cycles have no effect.
+    Exited,
+}
+
 pub struct Chip8 {
-    memory: [u8; 4096],
+    /// 4KB of memory, or 64KB on XO-CHIP.
+    memory: Vec<u8>,
 
     V: [u8; 16],
 
     I: u16,
     pc: u16,
 
-    /// Array that holds the screen content in a 64x32 format.
-    /// I.e. every 64th index marks the beginning of a new line.
-    pub gfx: [u8; 64 * 32],
+    display: Display,
+    /// The XO-CHIP planes selected by FN01 that drawing, clearing and
+    /// scrolling affect.
+    planes: u8,
 
     delay_timer: u8,
     sound_timer: u8,
@@ -38,186 +163,649 @@ pub struct Chip8 {
     sp: u16,
 
     key: [u8; 16],
+    /// Keys pressed since FX0A started waiting, as a bitmask.
+    key_presses: u16,
+    /// Keys pressed since the last call to `take_recent_keys`.
+    recent_keys: u16,
+
+    key_wait: KeyWait,
+    awaiting_vblank: bool,
+    exited: bool,
+
+    /// Instructions executed per second by `step_for`.
+    clock_speed: u32,
+    /// Where `load_rom` puts ROMs.
+    load_address: u16,
+    /// Time passed to `step_for` that was not yet used up by a full
+    /// instruction or timer tick, scaled by the respective frequency.
+    cycle_budget: u64,
+    timer_budget: u64,
+
+    /// The SUPER-CHIP RPL user flags, saved and restored by FX75/FX85.
+    flags: [u8; 16],
+
+    /// The XO-CHIP audio pattern buffer loaded by F002, played back
+    /// one bit at a time while the sound timer is active.
+    audio_pattern: [u8; 16],
+    /// The XO-CHIP playback pitch set by FX3A.
+    pitch: u8,
+
+    quirks: Quirks,
+
+    /// The random number generator used by CXNN.
+    rng: Box<dyn Rng>,
+    /// The value `rng` was last seeded with.
+    seed: u32,
+
+    /// Notified when the buzzer starts or stops.
+    audio_sink: Option<Box<dyn AudioSink>>,
+
+    /// Recent frames to rewind to, if enabled.
+    history: Option<History>,
+
+    /// Where executed instructions are logged, if enabled.
+    trace: Option<Trace>,
+}
 
-    awaiting_key_press: bool,
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
+        Chip8::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
        let mut game = Chip8 {
-            memory: [0; 4096],
+            memory: vec![0; if quirks.variant == Variant::XoChip { XOCHIP_MEMORY_SIZE } else { MEMORY_SIZE }],
             V: [0; 16],
             I: 0,
-            pc: 0x200,
-            gfx: [0; 64 * 32],
+            pc: DEFAULT_LOAD_ADDRESS,
+            display: Display::new(false),
+            planes: 1,
             delay_timer: 0,
             sound_timer: 0,
             stack: [0; 16],
             sp: 0,
             key: [0; 16],
-            awaiting_key_press: false,
+            key_presses: 0,
+            recent_keys: 0,
+            key_wait: KeyWait::Idle,
+            awaiting_vblank: false,
+            exited: false,
+            clock_speed: DEFAULT_CLOCK_SPEED,
+            load_address: DEFAULT_LOAD_ADDRESS,
+            cycle_budget: 0,
+            timer_budget: 0,
+            flags: [0; 16],
+            audio_pattern: [0; 16],
+            pitch: DEFAULT_PITCH,
+            quirks,
+            rng: Box::new(Xorshift::new(0)),
+            seed: 0,
+            audio_sink: None,
+            history: None,
+            trace: None,
        };
 
-       for (i, val) in FONT_SET.iter().enumerate() {
-           game.memory[i] = *val;
-       }
+       game.memory[FONT_ADDRESS..FONT_ADDRESS + FONT_SET.len()].copy_from_slice(&FONT_SET);
+       game.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
+       game.set_seed(initial_seed());
 
        game
     }
 
-    pub fn load<T: std::io::Read>(&mut self, reader: &mut T) -> std::io::Result<usize> {
-        reader.read(&mut self.memory[0x200..])
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
     }
 
-    pub fn cycle(&mut self) {
-        let opcode: u16 = (self.memory[self.pc as usize] as u16) << 8 | (self.memory[(self.pc + 1) as usize] as u16);
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
 
-        match opcode & 0xF000 {
-            0x0000 => {
-                match opcode {
-                    // 00E0 - Clears the screen.
-                    // TODO
-                    0x00E0 => {},
-                    // 00EE - Returns from a subroutine.
-                    0x00EE => {
-                        self.sp -= 1;
-                        self.pc = self.stack[self.sp as usize] + 2;
-                    },
-                    _ => panic!("Opcode {} not matched.", opcode),
-                };
+    pub fn i(&self) -> u16 {
+        self.I
+    }
+
+    /// The registers V0 to VF.
+    pub fn v(&self) -> &[u8; 16] {
+        &self.V
+    }
+
+    /// The return addresses currently on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Memory for debuggers and loaders to write to.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// A 64 bit FNV-1a hash of the screen resolution and content, for
+    /// comparing screens without keeping them around.
+    pub fn screen_hash(&self) -> u64 {
+        let size = [self.width() as u8, self.height() as u8];
+        size.iter().chain(self.display.pixels().iter()).fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01B3)
+        })
+    }
+
+    /// Writes the screen as a plain PBM image, with every pixel that
+    /// is set in any plane in black.
+    pub fn write_pbm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "P1")?;
+        writeln!(out, "{} {}", self.width(), self.height())?;
+        for row in self.display.rows() {
+            let line: Vec<&str> = row.iter().map(|pixel| if *pixel != 0 { "1" } else { "0" }).collect();
+            writeln!(out, "{}", line.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// The screen.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// The screen, to take the region that changed from it.
+    pub fn display_mut(&mut self) -> &mut Display {
+        &mut self.display
+    }
+
+    /// Width of the screen in pixels.
+    pub fn width(&self) -> usize {
+        self.display.width()
+    }
+
+    /// Height of the screen in pixels.
+    pub fn height(&self) -> usize {
+        self.display.height()
+    }
+
+    /// The XO-CHIP audio pattern buffer, 128 one bit samples.
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// The rate in Hz at which the samples of the audio pattern
+    /// buffer are played back, as set by FX3A.
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// Number of instructions `step_for` executes per second.
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
+
+    /// Sets the number of instructions `step_for` executes per second,
+    /// between 1 and `MAX_CLOCK_SPEED`.
+    pub fn set_clock_speed(&mut self, hz: u32) {
+        self.clock_speed = hz.clamp(1, MAX_CLOCK_SPEED);
+        self.cycle_budget = 0;
+    }
+
+    /// Whether the program has exited through 00FD.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The value the random number generator was last seeded with,
+    /// which is taken from the system clock unless set with `set_seed`.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Reseeds the random number generator used by CXNN, so that the
+    /// same seed always produces the same random numbers.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.rng.seed(seed);
+    }
+
+    /// Replaces the `Xorshift` generator used by CXNN. The new one is
+    /// seeded with the current seed.
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.rng = rng;
+        self.rng.seed(self.seed);
+    }
+
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    /// Sets where ROMs are loaded and execution starts, which is
+    /// `DEFAULT_LOAD_ADDRESS` unless the ROM was written for the
+    /// ETI-660.
+    pub fn set_load_address(&mut self, address: u16) {
+        self.load_address = address;
+        self.pc = address;
+    }
+
+    /// Copies `rom` to the load address and points the program
+    /// counter at it.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), LoadError> {
+        let start = self.load_address as usize;
+        let available = self.memory.len().saturating_sub(start);
+        if rom.len() > available {
+            return Err(LoadError::TooBig { size: rom.len(), available });
+        }
+
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.pc = self.load_address;
+        Ok(())
+    }
+
+    /// Reads `reader` to the end and loads it with `load_rom`.
+    pub fn load<R: Read>(&mut self, reader: &mut R) -> Result<(), LoadError> {
+        let mut rom = Vec::new();
+        reader.read_to_end(&mut rom)?;
+        self.load_rom(&rom)
+    }
+
+    /// Reads the file at `path` and loads it with `load_rom`.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LoadError> {
+        self.load(&mut File::open(path)?)
+    }
+
+    pub fn key_down(&mut self, key: Key) {
+        let index = key.index() as usize;
+        if self.key[index] == 0 {
+            self.key_presses |= 1 << index;
+        }
+        self.recent_keys |= 1 << index;
+        self.key[index] = 1;
+    }
+
+    pub fn key_up(&mut self, key: Key) {
+        self.key[key.index() as usize] = 0;
+    }
+
+    pub fn is_key_down(&self, key: Key) -> bool {
+        self.key[key.index() as usize] != 0
+    }
+
+    /// Sets the state of all keys at once. Bit N of `keys` is set if
+    /// the key with the hex value N is held down.
+    pub fn set_keypad(&mut self, keys: u16) {
+        for key in Key::all() {
+            if keys & (1 << key.index()) != 0 {
+                self.key_down(*key);
+            } else {
+                self.key_up(*key);
+            }
+        }
+    }
+
+    /// The state of all keys as a bitmask, see `set_keypad`.
+    pub fn keypad(&self) -> u16 {
+        self.key.iter().enumerate().fold(0, |keys, (i, key)| {
+            if *key != 0 { keys | 1 << i } else { keys }
+        })
+    }
+
+    /// The keys held down now or at any point since the last call, as
+    /// a bitmask like `keypad`. Catches keys that were pressed and
+    /// released again in between.
+    pub fn take_recent_keys(&mut self) -> u16 {
+        let keys = self.recent_keys | self.keypad();
+        self.recent_keys = 0;
+        keys
+    }
+
+    /// Counts down the delay and sound timers. Has to be called at
+    /// `TIMER_FREQUENCY`, which also marks the vertical blank that the
+    /// display wait quirk waits for, and the point at which the state
+    /// is recorded for rewinding.
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+            if self.sound_timer == 0 {
+                if let Some(ref mut sink) = self.audio_sink {
+                    sink.stop_tone();
+                }
+            }
+        }
+        self.awaiting_vblank = false;
+        self.record_frame();
+    }
+
+    /// Whether the buzzer sounds, i.e. the sound timer is non-zero.
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Sets the sink that is notified whenever the buzzer starts or
+    /// stops.
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(sink);
+    }
+
+    /// Runs a single 60Hz frame: executes up to `instructions_per_frame`
+    /// instructions and then ticks the timers.
+    ///
+    /// The frame ends early when the program waits for the vertical
+    /// blank or exits. On error the timers are not ticked.
+    pub fn run_frame(&mut self, instructions_per_frame: u32) -> Result<(), Chip8Error> {
+        self.run_cycles(instructions_per_frame)?;
+        self.tick_timers();
+        Ok(())
+    }
+
+    /// Advances the machine by `duration` of emulated time, executing
+    /// instructions at `clock_speed()` and ticking the timers at
+    /// `TIMER_FREQUENCY`. Fractions of an instruction or tick are
+    /// carried over to the next call.
+    pub fn step_for(&mut self, duration: Duration) -> Result<(), Chip8Error> {
+        let nanos = duration.as_secs() * NANOS_PER_SECOND + duration.subsec_nanos() as u64;
+        self.cycle_budget += nanos * self.clock_speed as u64;
+        self.timer_budget += nanos * TIMER_FREQUENCY as u64;
+        let cycles = self.cycle_budget / NANOS_PER_SECOND;
+        let ticks = self.timer_budget / NANOS_PER_SECOND;
+        self.cycle_budget %= NANOS_PER_SECOND;
+        self.timer_budget %= NANOS_PER_SECOND;
+
+        // Spread the instructions evenly between the timer ticks.
+        let mut executed = 0;
+        for tick in 0..ticks {
+            let due = cycles * (tick + 1) / ticks;
+            self.run_cycles((due - executed) as u32)?;
+            executed = due;
+            self.tick_timers();
+        }
+        self.run_cycles((cycles - executed) as u32)
+    }
+
+    /// Executes up to `count` instructions, stopping early when the
+    /// program waits for the vertical blank or exits.
+    fn run_cycles(&mut self, count: u32) -> Result<(), Chip8Error> {
+        for _ in 0..count {
+            match self.cycle()? {
+                StepOutcome::WaitingForVblank | StepOutcome::Exited => break,
+                StepOutcome::Executed | StepOutcome::WaitingForKey => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes a single instruction. Timers are not affected, see
+    /// `tick_timers`.
+    ///
+    /// On error the faulting instruction has no effect and the
+    /// program counter still points at it.
+    pub fn cycle(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.awaiting_vblank {
+            return Ok(StepOutcome::WaitingForVblank);
+        }
+
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+
+        self.check_memory(self.pc as usize, 2)?;
+        let opcode: u16 = (self.memory[self.pc as usize] as u16) << 8 | (self.memory[self.wrap(self.pc as usize + 1)] as u16);
+        let instruction = match Instruction::decode(opcode) {
+            Ok(instruction) if instruction.variant() <= self.quirks.variant => instruction,
+            // Including 0NNN, which calls the machine code routine at
+            // NNN. Only meaningful on the original hardware.
+            _ => return Err(self.invalid_opcode(opcode)),
+        };
+
+        // FX0A is only traced when it starts waiting, and instructions
+        // that fail aren't traced at all.
+        let trace_line = if self.key_wait == KeyWait::Idle { self.trace_line() } else { None };
+        self.execute(instruction)?;
+        if let Some(line) = trace_line {
+            self.write_trace(&line);
+        }
+
+        if self.exited {
+            Ok(StepOutcome::Exited)
+        } else if self.key_wait != KeyWait::Idle {
+            Ok(StepOutcome::WaitingForKey)
+        } else {
+            Ok(StepOutcome::Executed)
+        }
+    }
+
+    /// Executes `instruction`, which is at the program counter and
+    /// supported by the variant.
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        match instruction {
+            // 00E0 - Clears the screen.
+            // On XO-CHIP only the selected planes are cleared.
+            Instruction::Cls => {
+                self.display.clear(self.planes);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 00EE - Returns from a subroutine.
+            Instruction::Ret => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow { pc: self.pc });
+                }
+                self.sp -= 1;
+                self.pc = self.stack[self.sp as usize].wrapping_add(2);
+            },
+            // 00DN - Scrolls the display up by N pixels. (XO-CHIP)
+            Instruction::ScrollUp(n) => {
+                self.display.scroll(0, -(n as isize), self.planes);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 00CN - Scrolls the display down by N pixels. (SUPER-CHIP)
+            Instruction::ScrollDown(n) => {
+                self.display.scroll(0, n as isize, self.planes);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 00FB - Scrolls the display right by 4 pixels. (SUPER-CHIP)
+            Instruction::ScrollRight => {
+                self.display.scroll(4, 0, self.planes);
+                self.pc = self.pc.wrapping_add(2);
             },
+            // 00FC - Scrolls the display left by 4 pixels. (SUPER-CHIP)
+            Instruction::ScrollLeft => {
+                self.display.scroll(-4, 0, self.planes);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 00FD - Exits the interpreter. (SUPER-CHIP)
+            Instruction::Exit => self.exited = true,
+            // 00FE - Switches to the 64x32 low resolution mode. (SUPER-CHIP)
+            Instruction::Low => {
+                self.display = Display::new(false);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 00FF - Switches to the 128x64 high resolution mode. (SUPER-CHIP)
+            Instruction::High => {
+                self.display = Display::new(true);
+                self.pc = self.pc.wrapping_add(2);
+            },
+
             // 1NNN - Jumps to address NNN.
-            0x1000 => self.pc = opcode & 0x0FFF,
+            Instruction::Jp(nnn) => self.pc = nnn,
 
             // 2NNN - Calls subroutine at NNN.
-            0x2000 => {
+            Instruction::Call(nnn) => {
+                if self.sp as usize == self.stack.len() {
+                    return Err(Chip8Error::StackOverflow { pc: self.pc });
+                }
                 // Put the current routine on the stack.
                 self.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
-                self.pc = opcode & 0x0FFF;
+                self.pc = nnn;
             },
 
             // 3XNN - Skips the next instruction if VX equals NN. (Usually the next instruction is a jump to skip a code block)
-            0x3000 => {
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-
-                if self.V[x] == nn {
-                    self.pc += 2;
+            Instruction::SeVxByte { x, nn } => {
+                if self.V[x as usize] == nn {
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             },
 
             // 4XNN - Skips the next instruction if VX doesn't equal NN. (Usually the next instruction is a jump to skip a code block)
-            0x4000 => {
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-
-                if self.V[x] != nn {
-                    self.pc += 2;
+            Instruction::SneVxByte { x, nn } => {
+                if self.V[x as usize] != nn {
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             },
 
             // 5XY0 - Skips the next instruction if VX equals VY. (Usually the next instruction is a jump to skip a code block)
-            0x5000 => {
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let y = ((opcode & 0x00F0) >> 4) as usize;
-
-                if self.V[x] == self.V[y] {
-                    self.pc += 2;
+            Instruction::SeVxVy { x, y } => {
+                if self.V[x as usize] == self.V[y as usize] {
+                    self.skip_next();
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 5XY2 - Stores VX to VY in memory starting at address I.
+            // I is not changed. Registers are stored in descending
+            // order if X is greater than Y. (XO-CHIP)
+            Instruction::SaveRange { x, y } => {
+                let registers = register_range(x as usize, y as usize);
+                self.check_memory(self.I as usize, registers.len())?;
+                for (offset, r) in registers.into_iter().enumerate() {
+                    let address = self.wrap(self.I as usize + offset);
+                    self.memory[address] = self.V[r];
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 5XY3 - Fills VX to VY from memory starting at address I.
+            // I is not changed. (XO-CHIP)
+            Instruction::LoadRange { x, y } => {
+                let registers = register_range(x as usize, y as usize);
+                self.check_memory(self.I as usize, registers.len())?;
+                for (offset, r) in registers.into_iter().enumerate() {
+                    self.V[r] = self.memory[self.wrap(self.I as usize + offset)];
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             },
 
             // 6XNN - Sets VX to NN.
-            0x6000 => {
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-
-                self.V[x] = nn;
-                self.pc += 2;
+            Instruction::LdVxByte { x, nn } => {
+                self.V[x as usize] = nn;
+                self.pc = self.pc.wrapping_add(2);
             },
 
             // 7XNN - Adds NN to VX. (Carry flag is not changed)
-            0x7000 => {
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-
-                self.V[x] = self.V[x].overflowing_add(nn).0;
-                self.pc += 2;
-            },
-
-            0x8000 => {
-                self.pc += 2;
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let y = ((opcode & 0x00F0) >> 4) as usize;
-                match opcode & 0x000F {
-                    // 8XY0 - Sets VX to the value of VY.
-                    0 => self.V[x] = self.V[y],
-                    // 8XY1 - Sets VX to VX or VY. (Bitwise OR operation).
-                    1 => self.V[x] |= self.V[y],
-                    // 8XY2 - Sets VX to VX and VY. (Bitwise AND operation)
-                    2 => self.V[x] &= self.V[y],
-                    // 8XY3 - Sets VX to VX xor VY.
-                    3 => self.V[x] ^= self.V[y],
-                    // 8XY4 - Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
-                    4 => {
-                        let (vx, carry) = self.V[x].overflowing_add(self.V[y]);
-                        self.V[x] = vx;
-                        self.V[0xF] = if carry { 1 } else { 0 };
-                    },
-                    // 8XY5 - VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                    5 => {
-                        let (vx, carry) = self.V[x].overflowing_sub(self.V[y]);
-                        self.V[x] = vx;
-                        self.V[0xF] = if carry { 1 } else { 0 };
-                    },
-                    // 8XY6 - Shifts VY right by one and copies the result to VX. VF is set to the value of the least significant bit of VY before the shift.
-                    6 => {
-                        self.V[0xF] = self.V[y] & 1;
-                        self.V[y] >>= 1;
-                        self.V[x] = self.V[y];
-                    },
-                    // 8XY7 - Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                    7 => {
-                        if self.V[y] > self.V[x] {
-                            self.V[0xF] = 1;
-                        } else {
-                            self.V[0xF] = 0;
-                        }
+            Instruction::AddVxByte { x, nn } => {
+                self.V[x as usize] = self.V[x as usize].wrapping_add(nn);
+                self.pc = self.pc.wrapping_add(2);
+            },
 
-                        self.V[x] = self.V[y] - self.V[x];
-                    },
-                    // 8XYE - Shifts VY left by one and copies the result to VX. VF is set to the value of the most significant bit of VY before the shift.
-                    0xE => {
-                        self.V[0xF] = self.V[y] & 0b10000000;
-                        self.V[y] <<= 1;
-                        self.V[x] = self.V[y];
-                    },
-                    _ => panic!("Opcode {} not matched.", opcode),
-                };
+            // VF is always written after VX, so that the flag wins
+            // when VF itself is the target register.
+
+            // 8XY0 - Sets VX to the value of VY.
+            Instruction::LdVxVy { x, y } => {
+                self.V[x as usize] = self.V[y as usize];
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XY1 - Sets VX to VX or VY. (Bitwise OR operation). VF is reset to 0.
+            Instruction::Or { x, y } => {
+                self.V[x as usize] |= self.V[y as usize];
+                if self.quirks.vf_reset {
+                    self.V[0xF] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XY2 - Sets VX to VX and VY. (Bitwise AND operation). VF is reset to 0.
+            Instruction::And { x, y } => {
+                self.V[x as usize] &= self.V[y as usize];
+                if self.quirks.vf_reset {
+                    self.V[0xF] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XY3 - Sets VX to VX xor VY. VF is reset to 0.
+            Instruction::Xor { x, y } => {
+                self.V[x as usize] ^= self.V[y as usize];
+                if self.quirks.vf_reset {
+                    self.V[0xF] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XY4 - Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
+            Instruction::AddVxVy { x, y } => {
+                let (vx, carry) = self.V[x as usize].overflowing_add(self.V[y as usize]);
+                self.V[x as usize] = vx;
+                self.V[0xF] = carry as u8;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XY5 - VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+            Instruction::Sub { x, y } => {
+                let (vx, borrow) = self.V[x as usize].overflowing_sub(self.V[y as usize]);
+                self.V[x as usize] = vx;
+                self.V[0xF] = !borrow as u8;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XY6 - Shifts VY right by one and copies the result to VX. VF is set to the value of the least significant bit of VY before the shift.
+            // Without the shift quirk VX is shifted in place.
+            Instruction::Shr { x, y } => {
+                let vy = if self.quirks.shift_uses_vy { self.V[y as usize] } else { self.V[x as usize] };
+                self.V[x as usize] = vy >> 1;
+                self.V[0xF] = vy & 1;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XY7 - Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+            Instruction::Subn { x, y } => {
+                let (vx, borrow) = self.V[y as usize].overflowing_sub(self.V[x as usize]);
+                self.V[x as usize] = vx;
+                self.V[0xF] = !borrow as u8;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // 8XYE - Shifts VY left by one and copies the result to VX. VF is set to the value of the most significant bit of VY before the shift.
+            Instruction::Shl { x, y } => {
+                let vy = if self.quirks.shift_uses_vy { self.V[y as usize] } else { self.V[x as usize] };
+                self.V[x as usize] = vy << 1;
+                self.V[0xF] = vy >> 7;
+                self.pc = self.pc.wrapping_add(2);
             },
 
             // 9XY0 - Skips the next instruction if VX doesn't equal VY. (Usually the next instruction is a jump to skip a code block)
-            0x9000 => {
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let y = ((opcode & 0x00F0) >> 4) as usize;
-
-                if self.V[x] != self.V[y] {
-                    self.pc += 2;
+            Instruction::SneVxVy { x, y } => {
+                if self.V[x as usize] != self.V[y as usize] {
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             },
 
             // ANNN - Sets I to the address NNN.
-            0xA000 => {
-                self.I = opcode & 0x0FFF;
-                self.pc += 2;
+            Instruction::LdI(nnn) => {
+                self.I = nnn;
+                self.pc = self.pc.wrapping_add(2);
             },
 
             // BNNN - Jumps to the address NNN plus V0.
-            0xB000 => self.pc = opcode & 0x0FFF + self.V[0] as u16,
+            // With the jump quirk this is BXNN, jumping to XNN plus VX.
+            Instruction::JpV0(nnn) => {
+                let x = if self.quirks.jump_uses_vx { (nnn >> 8) as usize } else { 0 };
+                self.pc = nnn + self.V[x] as u16;
+            },
+
+            // CXNN - Sets VX to the result of a bitwise and operation on a
+            // random number (Typically: 0 to 255) and NN.
+            Instruction::Rnd { x, nn } => {
+                self.V[x as usize] = self.rng.next_byte() & nn;
+                self.pc = self.pc.wrapping_add(2);
+            },
 
             // DXYN - Draws a sprite at coordinate (VX, VY) that has
             // a width of 8 pixels and a height of N pixels. Each row
@@ -226,94 +814,333 @@ impl Chip8 {
             // of this instruction. As described above, VF is set to 1
             // if any screen pixels are flipped from set to unset when
             // the sprite is drawn, and to 0 if that doesn’t happen
-            0xD000 => {
+            // DXY0 - Draws a 16x16 sprite, two bytes per row. (SUPER-CHIP)
+            Instruction::Drw { x, y, n } => {
+                let N = n as usize;
+                let (sprite_width, sprite_height) = if N == 0 && self.superchip() {
+                    (16, 16)
+                } else {
+                    (8, N)
+                };
+                let bytes_per_row = sprite_width / 8;
+                // On XO-CHIP the sprite holds one block of data for each
+                // selected plane, one after the other.
+                let plane_bytes = sprite_height * bytes_per_row;
+                self.check_memory(self.I as usize, plane_bytes * self.planes.count_ones() as usize)?;
+
+                let width = self.width();
+                let height = self.height();
+                // The starting coordinate always wraps around, only the
+                // sprite itself is affected by the clipping quirk.
+                let X = self.V[x as usize] as usize % width;
+                let Y = self.V[y as usize] as usize % height;
+
                 // Reset collision flag.
                 self.V[0xF] = 0;
 
-                let X = ((opcode & 0x0F00) >> 8) as u16;
-                let Y = ((opcode & 0x00F0) >> 4) as u16;
-                let N = (opcode & 0x000F) as u16;
-
-                for y in 0..N {
-                    // Gets the sprite, e.g. 0b00111100.
-                    let sprite = self.memory[(self.I + y as u16) as usize];
-                    for x in 0..8 {
-                        // Gets the pixel by masking with a single bit shifted
-                        // to the correct position.
-                        // I.e. to find if the 5th pixel in 0b00111100 is set,
-                        // we mask with 0b10000000 >> 4, so 0b00001000.
-                        let pixel = sprite & (0x80 >> x);
-                        // The gfx position we want to write to.
-                        let pos = ((y + Y) * 64 + x + X) as usize;
-
-                        // Set flag for collision detection.
-                        if pixel != 0 && self.gfx[pos] != 0 {
-                            self.V[0xF] = 1;
+                let planes = self.planes;
+                let mut address = self.I as usize;
+                for plane in [0b01, 0b10].iter().filter(|plane| planes & **plane != 0) {
+                    for y in 0..sprite_height {
+                        let mut row = Y + y;
+                        if row >= height {
+                            if self.quirks.clip_sprites {
+                                break;
+                            }
+                            row %= height;
+                        }
+                        // Gets the sprite row left-aligned in 16 bits, e.g.
+                        // 0b00111100_00000000 for a regular 8 pixel sprite.
+                        let row_address = address + y * bytes_per_row;
+                        let mut sprite = (self.memory[self.wrap(row_address)] as u16) << 8;
+                        if bytes_per_row == 2 {
+                            sprite |= self.memory[self.wrap(row_address + 1)] as u16;
                         }
+                        for x in 0..sprite_width {
+                            let mut col = X + x;
+                            if col >= width {
+                                if self.quirks.clip_sprites {
+                                    break;
+                                }
+                                col %= width;
+                            }
+                            // Gets the pixel by masking with a single bit shifted
+                            // to the correct position.
+                            // I.e. to find if the 5th pixel in 0b00111100_00000000
+                            // is set, we mask with 0x8000 >> 4, so 0b00001000_00000000.
+                            let pixel = sprite & (0x8000 >> x);
+
+                            // Unset sprite pixels leave the screen untouched.
+                            if pixel == 0 {
+                                continue;
+                            }
 
-                        // Set the pixel.
-                        if pixel == 0 {
-                           self.gfx[pos] = 0;
-                        } else {
-                           self.gfx[pos] = 1;
-                        };
+                            // Flip the pixel and set the flag for collision detection.
+                            if self.display.flip(col, row, *plane) {
+                                self.V[0xF] = 1;
+                            }
+                        }
                     }
+                    address += plane_bytes;
+                }
+
+                if self.quirks.display_wait {
+                    self.awaiting_vblank = true;
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+
+            // EX9E - Skips the next instruction if the key stored in VX is pressed.
+            // Only the lowest nibble of VX addresses a key.
+            Instruction::Skp { x } => {
+                if self.key[(self.V[x as usize] & 0xF) as usize] != 0 {
+                    self.skip_next();
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // EXA1 - Skips the next instruction if the key stored in VX isn't pressed.
+            Instruction::Sknp { x } => {
+                if self.key[(self.V[x as usize] & 0xF) as usize] == 0 {
+                    self.skip_next();
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             },
 
-            0xF000 => {
-                // Extract the X from FX..
-                let x = ((opcode & 0x0F00) >> 8) as u8;
-                match opcode & 0x00FF {
-                    // FX07 - Sets VX to the value of the delay timer.
-                    0x07 => {
-                        self.V[x as usize] = self.delay_timer;
-                        self.pc += 2;
+            // F000 NNNN - Sets I to the 16 bit address NNNN stored
+            // in the following word. (XO-CHIP)
+            Instruction::LdILong => {
+                self.check_memory(self.pc as usize + 2, 2)?;
+                let address = self.pc as usize + 2;
+                self.I = (self.memory[self.wrap(address)] as u16) << 8 | self.memory[self.wrap(address + 1)] as u16;
+                self.pc = self.pc.wrapping_add(4);
+            },
+            // FN01 - Selects the planes drawing, clearing and
+            // scrolling operate on, as a bitmask of N. (XO-CHIP)
+            Instruction::Plane(n) => {
+                self.planes = n & 0b11;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // F002 - Loads 16 bytes starting at I into the audio
+            // pattern buffer. (XO-CHIP)
+            Instruction::Audio => {
+                let i = self.I as usize;
+                self.check_memory(i, 16)?;
+                let pattern: Vec<u8> = (0..16).map(|offset| self.memory[self.wrap(i + offset)]).collect();
+                self.audio_pattern.copy_from_slice(&pattern);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX07 - Sets VX to the value of the delay timer.
+            Instruction::LdVxDt { x } => {
+                self.V[x as usize] = self.delay_timer;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX0A - A key press is awaited, and then stored in VX.
+            // Blocking Operation. All instruction halted until
+            // next key event. Like on the COSMAC VIP, the key has
+            // to be pressed after FX0A started and released again.
+            Instruction::LdVxK { x } => {
+                match self.key_wait {
+                    KeyWait::Idle => {
+                        self.key_presses = 0;
+                        self.key_wait = KeyWait::Press;
                     },
-                    // FX0A - A key press is awaited, and then stored in VX.
-                    // Blocking Operation. All instruction halted until
-                    // next key event.
-                    0x0A => {
-                        self.awaiting_key_press = true;
-                        for (i, key) in self.key.iter().enumerate() {
-                            if *key != 0 {
-                                self.awaiting_key_press = false;
-                                self.pc += 2;
-                                self.V[x as usize] = i as u8;
-                                break;
-                            }
-                        }
+                    KeyWait::Press if self.key_presses != 0 => {
+                        self.key_wait = KeyWait::Release(self.key_presses.trailing_zeros() as u8);
                     },
-                    _ => panic!("Opcode {} not matched.", opcode),
-                };
+                    _ => {},
+                }
+                if let KeyWait::Release(key) = self.key_wait {
+                    if self.key[key as usize] == 0 {
+                        self.key_wait = KeyWait::Idle;
+                        self.V[x as usize] = key;
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                }
+            },
+            // FX15 - Sets the delay timer to VX.
+            Instruction::LdDtVx { x } => {
+                self.delay_timer = self.V[x as usize];
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX18 - Sets the sound timer to VX.
+            Instruction::LdStVx { x } => {
+                let was_beeping = self.is_beeping();
+                self.sound_timer = self.V[x as usize];
+                if was_beeping != self.is_beeping() {
+                    if let Some(ref mut sink) = self.audio_sink {
+                        if was_beeping { sink.stop_tone() } else { sink.start_tone() }
+                    }
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX1E - Adds VX to I. VF is not affected.
+            Instruction::AddIVx { x } => {
+                self.I = self.I.wrapping_add(self.V[x as usize] as u16);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX29 - Sets I to the location of the sprite for the
+            // character in VX. Characters 0-F (in hexadecimal) are
+            // represented by a 4x5 font.
+            Instruction::LdFVx { x } => {
+                self.I = (FONT_ADDRESS + (self.V[x as usize] & 0xF) as usize * 5) as u16;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX30 - Sets I to the location of the 8x10 sprite for
+            // the character in VX. (SUPER-CHIP)
+            Instruction::LdHfVx { x } => {
+                self.I = (BIG_FONT_ADDRESS + (self.V[x as usize] & 0xF) as usize * 10) as u16;
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX3A - Sets the audio playback pitch to VX. (XO-CHIP)
+            Instruction::Pitch { x } => {
+                self.pitch = self.V[x as usize];
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX33 - Stores the binary-coded decimal representation
+            // of VX, with the most significant of three digits at
+            // the address in I, the middle digit at I plus 1, and
+            // the least significant digit at I plus 2.
+            Instruction::LdBVx { x } => {
+                let vx = self.V[x as usize];
+                let i = self.I as usize;
+                self.check_memory(i, 3)?;
+                for (offset, digit) in [vx / 100, vx / 10 % 10, vx % 10].iter().enumerate() {
+                    let address = self.wrap(i + offset);
+                    self.memory[address] = *digit;
+                }
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX55 - Stores V0 to VX (including VX) in memory
+            // starting at address I. I is increased by X + 1.
+            Instruction::LdIVx { x } => {
+                self.check_memory(self.I as usize, x as usize + 1)?;
+                for r in 0..(x as usize + 1) {
+                    let address = self.wrap(self.I as usize + r);
+                    self.memory[address] = self.V[r];
+                }
+                self.increment_i_after_load_store(x);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX65 - Fills V0 to VX (including VX) with values from
+            // memory starting at address I. I is increased by X + 1.
+            Instruction::LdVxI { x } => {
+                self.check_memory(self.I as usize, x as usize + 1)?;
+                for r in 0..(x as usize + 1) {
+                    self.V[r] = self.memory[self.wrap(self.I as usize + r)];
+                }
+                self.increment_i_after_load_store(x);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX75 - Stores V0 to VX (including VX) in the RPL user flags. (SUPER-CHIP)
+            Instruction::LdRVx { x } => {
+                self.flags[..x as usize + 1].copy_from_slice(&self.V[..x as usize + 1]);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            // FX85 - Fills V0 to VX (including VX) from the RPL user flags. (SUPER-CHIP)
+            Instruction::LdVxR { x } => {
+                self.V[..x as usize + 1].copy_from_slice(&self.flags[..x as usize + 1]);
+                self.pc = self.pc.wrapping_add(2);
             },
-            _ => panic!("Opcode {} not matched.", opcode),
         };
 
-        // Count down timers.
-        if self.delay_timer > 0 && !self.awaiting_key_press {
-            self.delay_timer -= 1;
+        Ok(())
+    }
+
+    fn increment_i_after_load_store(&mut self, x: u8) {
+        match self.quirks.load_store {
+            LoadStoreQuirk::IncrementByXPlusOne => self.I = self.I.wrapping_add(x as u16 + 1),
+            LoadStoreQuirk::IncrementByX => self.I = self.I.wrapping_add(x as u16),
+            LoadStoreQuirk::Unchanged => {},
         }
-        if self.sound_timer > 0 && !self.awaiting_key_press {
-            self.sound_timer -= 1;
+    }
+
+    fn superchip(&self) -> bool {
+        self.quirks.variant != Variant::Chip8
+    }
+
+    fn xochip(&self) -> bool {
+        self.quirks.variant == Variant::XoChip
+    }
+
+    /// Skips over the next instruction, which is twice as long if it
+    /// is the XO-CHIP F000 NNNN long load.
+    fn skip_next(&mut self) {
+        let next = self.pc as usize + 2;
+        if self.xochip() && self.memory[self.wrap(next)] == 0xF0 && self.memory[self.wrap(next + 1)] == 0x00 {
+            self.pc = self.pc.wrapping_add(4);
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    fn invalid_opcode(&self, opcode: u16) -> Chip8Error {
+        Chip8Error::InvalidOpcode { pc: self.pc, opcode }
+    }
+
+    /// The address in memory of byte `address` of an access, which
+    /// wraps around at the end of the 64K of XO-CHIP memory.
+    fn wrap(&self, address: usize) -> usize {
+        address & (self.memory.len() - 1)
+    }
+
+    /// Makes sure that `len` bytes starting at `start` lie within memory.
+    /// Accesses never fault on XO-CHIP, where they wrap around instead.
+    fn check_memory(&self, start: usize, len: usize) -> Result<(), Chip8Error> {
+        if self.memory.len() < XOCHIP_MEMORY_SIZE && start + len > self.memory.len() {
+            return Err(Chip8Error::MemoryFault {
+                pc: self.pc,
+                address: start.max(self.memory.len()),
+            });
         }
+        Ok(())
     }
 }
 
 #[test]
-#[ignore]
+// 00E0 - Clears the screen.
 fn test_00E0() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xE0;
+    game.display.pixels_mut()[0] = 1;
+    game.display.pixels_mut()[64 * 32 - 1] = 1;
+
+    game.cycle().unwrap();
+
+    assert!(game.display.pixels().iter().all(|pixel| *pixel == 0));
+    assert_eq!(game.pc, 0x202);
 }
 
 #[test]
-#[ignore]
+// 00EE - Returns from a subroutine.
 fn test_00EE() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x23;
+    game.memory[0x201] = 0x00;
+    game.memory[0x300] = 0x00;
+    game.memory[0x301] = 0xEE;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x300);
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.sp, 0);
+    assert_eq!(game.pc, 0x202);
 }
 
 #[test]
-#[ignore]
+// 1NNN - Jumps to address NNN.
 fn test_1NNN() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x13;
+    game.memory[0x201] = 0x45;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.sp, 0);
+    assert_eq!(game.pc, 0x345);
 }
 
 #[test]
@@ -322,7 +1149,7 @@ fn test_2NNN() {
     game.memory[0x200] = 0x21;
     game.memory[0x201] = 0x23;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.stack[0], 0x200);
     assert_eq!(game.stack[1], 0);
@@ -344,11 +1171,11 @@ fn test_3XNN() {
     game.V[1] = 0x42;
     game.V[2] = 0x22;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x204);
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x206);
 }
@@ -367,11 +1194,11 @@ fn test_4XNN() {
     game.V[1] = 0x22;
     game.V[2] = 0x42;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x204);
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x206);
 }
@@ -392,11 +1219,11 @@ fn test_5XY0() {
     game.V[3] = 0x22;
     game.V[4] = 0x42;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x204);
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x206);
 }
@@ -408,7 +1235,7 @@ fn test_6XNN() {
     game.memory[0x200] = 0x61;
     game.memory[0x201] = 0x23;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.V[1], 0x23);
     assert_eq!(game.pc, 0x202);
@@ -423,13 +1250,13 @@ fn test_7XNN() {
     game.memory[0x202] = 0x71;
     game.memory[0x203] = 0xFF;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.V[0xF], 0);
     assert_eq!(game.V[1], 0x23);
     assert_eq!(game.pc, 0x202);
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.V[0xF], 0);
     assert_eq!(game.V[1], 0x22);
@@ -444,7 +1271,7 @@ fn test_8XY0() {
     game.memory[0x201] = 0x20;
     game.V[0x2] = 0x42;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.V[0x2], 0x42);
     assert_eq!(game.V[0x1], 0x42);
@@ -452,55 +1279,196 @@ fn test_8XY0() {
 }
 
 #[test]
-// 8XY4 - Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
-fn test_8XY4() {
+// 8XY1 - Sets VX to VX or VY. (Bitwise OR operation). VF is reset to 0.
+fn test_8XY1() {
     let mut game = Chip8::new();
     game.memory[0x200] = 0x81;
-    game.memory[0x201] = 0x24;
-    game.memory[0x202] = 0x81;
-    game.memory[0x203] = 0x34;
-    game.V[0x1] = 0x42;
-    game.V[0x2] = 0x16;
-    game.V[0x3] = 0xFF;
+    game.memory[0x201] = 0x21;
+    game.V[0x1] = 0b1100;
+    game.V[0x2] = 0b0110;
+    game.V[0xF] = 1;
 
-    game.cycle();
+    game.cycle().unwrap();
 
+    assert_eq!(game.V[0x1], 0b1110);
     assert_eq!(game.V[0xF], 0);
-    assert_eq!(game.V[0x1], 0x58);
     assert_eq!(game.pc, 0x202);
-
-    game.cycle();
-
-    assert_eq!(game.V[0xF], 1);
-    assert_eq!(game.V[0x1], 0x57);
-    assert_eq!(game.pc, 0x204);
 }
 
 #[test]
-// 8XY5 - VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-fn test_8XY5() {
+// 8XY2 - Sets VX to VX and VY. (Bitwise AND operation). VF is reset to 0.
+fn test_8XY2() {
     let mut game = Chip8::new();
     game.memory[0x200] = 0x81;
-    game.memory[0x201] = 0x25;
-    game.memory[0x202] = 0x81;
-    game.memory[0x203] = 0x35;
-    game.V[0x1] = 0x42;
-    game.V[0x2] = 0x16;
-    game.V[0x3] = 0xFF;
+    game.memory[0x201] = 0x22;
+    game.V[0x1] = 0b1100;
+    game.V[0x2] = 0b0110;
+    game.V[0xF] = 1;
 
-    game.cycle();
+    game.cycle().unwrap();
 
+    assert_eq!(game.V[0x1], 0b0100);
     assert_eq!(game.V[0xF], 0);
-    assert_eq!(game.V[0x1], 0x26);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// 8XY3 - Sets VX to VX xor VY. VF is reset to 0.
+fn test_8XY3() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x23;
+    game.V[0x1] = 0b1100;
+    game.V[0x2] = 0b0110;
+    game.V[0xF] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x1], 0b1010);
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// 8XY4 - Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
+fn test_8XY4() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x24;
+    game.memory[0x202] = 0x81;
+    game.memory[0x203] = 0x34;
+    game.V[0x1] = 0x42;
+    game.V[0x2] = 0x16;
+    game.V[0x3] = 0xFF;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.V[0x1], 0x58);
+    assert_eq!(game.pc, 0x202);
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 1);
+    assert_eq!(game.V[0x1], 0x57);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// 8XY5 - VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+fn test_8XY5() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x25;
+    game.memory[0x202] = 0x81;
+    game.memory[0x203] = 0x35;
+    game.V[0x1] = 0x42;
+    game.V[0x2] = 0x16;
+    game.V[0x3] = 0xFF;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 1);
+    assert_eq!(game.V[0x1], 0x2C);
+    assert_eq!(game.pc, 0x202);
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.V[0x1], 0x2D);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// 8XY6 - Shifts VY right by one and copies the result to VX. VF is set to the value of the least significant bit of VY before the shift.
+fn test_8XY6() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x26;
+    game.memory[0x202] = 0x81;
+    game.memory[0x203] = 0x36;
+    game.V[0x2] = 0b0000_0101;
+    game.V[0x3] = 0b1000_0100;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 1);
+    assert_eq!(game.V[0x1], 0b0000_0010);
+    assert_eq!(game.V[0x2], 0b0000_0101);
     assert_eq!(game.pc, 0x202);
 
-    game.cycle();
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.V[0x1], 0b0100_0010);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// 8XY7 - Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+fn test_8XY7() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x27;
+    game.memory[0x202] = 0x81;
+    game.memory[0x203] = 0x37;
+    game.V[0x1] = 0x16;
+    game.V[0x2] = 0x42;
+    game.V[0x3] = 0x01;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 1);
+    assert_eq!(game.V[0x1], 0x2C);
+    assert_eq!(game.pc, 0x202);
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.V[0x1], 0xD5);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// 8XYE - Shifts VY left by one and copies the result to VX. VF is set to the value of the most significant bit of VY before the shift.
+fn test_8XYE() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x2E;
+    game.memory[0x202] = 0x81;
+    game.memory[0x203] = 0x3E;
+    game.V[0x2] = 0b1000_0101;
+    game.V[0x3] = 0b0100_0001;
+
+    game.cycle().unwrap();
 
     assert_eq!(game.V[0xF], 1);
-    assert_eq!(game.V[0x1], 0x27);
+    assert_eq!(game.V[0x1], 0b0000_1010);
+    assert_eq!(game.V[0x2], 0b1000_0101);
+    assert_eq!(game.pc, 0x202);
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.V[0x1], 0b1000_0010);
     assert_eq!(game.pc, 0x204);
 }
 
+#[test]
+// 8XY4 with VF as the target register - the flag overwrites the sum.
+fn test_8FY4() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x8F;
+    game.memory[0x201] = 0x14;
+    game.V[0x1] = 0xFF;
+    game.V[0xF] = 0x02;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 1);
+    assert_eq!(game.pc, 0x202);
+}
+
 #[test]
 // 9XY0 - Skips the next instruction if VX doesn't equal VY. (Usually the next instruction is a jump to skip a code block)
 fn test_9XY0() {
@@ -517,29 +1485,60 @@ fn test_9XY0() {
     game.V[3] = 0x42;
     game.V[4] = 0x42;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x204);
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.pc, 0x206);
 }
+
 #[test]
 fn test_ANNN() {
     let mut game = Chip8::new();
     game.memory[0x200] = 0xA1;
     game.memory[0x201] = 0x23;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.I, 0x123);
     assert_eq!(game.pc, 0x202);
 }
 
 #[test]
-#[ignore]
+// BNNN - Jumps to the address NNN plus V0.
 fn test_BNNN() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xB3;
+    game.memory[0x201] = 0x00;
+    game.V[0x0] = 0x42;
+    game.V[0x3] = 0x10;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x342);
+}
+
+#[test]
+// CXNN - Sets VX to the result of a bitwise and operation on a random number and NN.
+fn test_CXNN() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xC1;
+    game.memory[0x201] = 0x0F;
+    game.memory[0x202] = 0xC2;
+    game.memory[0x203] = 0x00;
+    game.V[0x2] = 0x42;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x1] & 0xF0, 0);
+    assert_eq!(game.pc, 0x202);
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x2], 0);
+    assert_eq!(game.pc, 0x204);
 }
 
 #[test]
@@ -555,15 +1554,15 @@ fn test_DXYN() {
 
     game.I = 0x210;
 
-    game.cycle();
+    game.cycle().unwrap();
 
-    assert_eq!(game.gfx[0..8], [
+    assert_eq!(game.display.pixels()[0..8], [
                0,0,0,1,1,0,0,0, ]);
-    assert_eq!(game.gfx[64..64 + 8], [
+    assert_eq!(game.display.pixels()[64..64 + 8], [
                0,0,1,1,1,1,0,0, ]);
-    assert_eq!(game.gfx[2 * 64..2 * 64 + 8], [
+    assert_eq!(game.display.pixels()[2 * 64..2 * 64 + 8], [
                0,1,1,1,1,1,1,0, ]);
-    assert_eq!(game.gfx[3 * 64..3 * 64 + 8], [
+    assert_eq!(game.display.pixels()[3 * 64..3 * 64 + 8], [
                1,1,1,1,1,1,1,1, ]);
     assert_eq!(game.I, 0x210);
     assert_eq!(game.pc, 0x202);
@@ -580,22 +1579,162 @@ fn test_DXYN() {
 
     game.I = 0x210;
 
-    game.cycle();
+    game.cycle().unwrap();
 
-    assert_eq!(game.gfx[0..8], [
+    assert_eq!(game.display.pixels()[0..8], [
                1,1,1,1,0,0,0,0, ]);
-    assert_eq!(game.gfx[64..64 + 8], [
+    assert_eq!(game.display.pixels()[64..64 + 8], [
                1,0,0,1,0,0,0,0, ]);
-    assert_eq!(game.gfx[2 * 64..2 * 64 + 8], [
+    assert_eq!(game.display.pixels()[2 * 64..2 * 64 + 8], [
                1,0,0,1,0,0,0,0, ]);
-    assert_eq!(game.gfx[3 * 64..3 * 64 + 8], [
+    assert_eq!(game.display.pixels()[3 * 64..3 * 64 + 8], [
                1,0,0,1,0,0,0,0, ]);
-    assert_eq!(game.gfx[4 * 64..4 * 64 + 8], [
+    assert_eq!(game.display.pixels()[4 * 64..4 * 64 + 8], [
                1,1,1,1,0,0,0,0, ]);
     assert_eq!(game.I, 0x210);
     assert_eq!(game.pc, 0x202);
 }
 
+#[test]
+// DXYN - Sprites are XORed onto the screen, VF is set on collision.
+fn test_DXYN_xor() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xD0;
+    game.memory[0x201] = 0x01;
+    game.memory[0x300] = 0b1100_0000;
+    game.I = 0x300;
+    game.display.pixels_mut()[1] = 1;
+    game.display.pixels_mut()[2] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[0..4], [1, 0, 1, 0]);
+    assert_eq!(game.V[0xF], 1);
+
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xD0;
+    game.memory[0x201] = 0x01;
+    game.memory[0x300] = 0b1100_0000;
+    game.I = 0x300;
+    game.display.pixels_mut()[2] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[0..4], [1, 1, 1, 0]);
+    assert_eq!(game.V[0xF], 0);
+}
+
+#[test]
+// DXYN - Drawing the same sprite twice erases it again.
+fn test_DXYN_erase() {
+    let mut game = Chip8::with_quirks(Quirks::chip48());
+    game.memory[0x200] = 0xD0;
+    game.memory[0x201] = 0x15;
+    game.memory[0x202] = 0xD0;
+    game.memory[0x203] = 0x15;
+    game.I = 0x0;
+    game.V[0x0] = 10;
+    game.V[0x1] = 7;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 0);
+    assert!(game.display.pixels().iter().any(|pixel| *pixel != 0));
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 1);
+    assert!(game.display.pixels().iter().all(|pixel| *pixel == 0));
+}
+
+#[test]
+// DXYN - Sprites at the edge of the screen are clipped or wrapped around.
+fn test_DXYN_edges() {
+    for &(quirks, wrapped) in [(Quirks::chip48(), 0), (Quirks::xochip(), 1)].iter() {
+        let mut game = Chip8::with_quirks(quirks);
+        game.memory[0x200] = 0xD0;
+        game.memory[0x201] = 0x14;
+        game.memory[0x300] = 0xFF;
+        game.memory[0x301] = 0xFF;
+        game.memory[0x302] = 0xFF;
+        game.memory[0x303] = 0xFF;
+        game.I = 0x300;
+        game.V[0x0] = 60;
+        game.V[0x1] = 30;
+
+        game.cycle().unwrap();
+
+        assert_eq!(game.display.pixels()[30 * 64 + 60..31 * 64], [1, 1, 1, 1]);
+        assert_eq!(game.display.pixels()[31 * 64 + 60..32 * 64], [1, 1, 1, 1]);
+        assert_eq!(game.display.pixels()[30 * 64..30 * 64 + 5], [wrapped, wrapped, wrapped, wrapped, 0]);
+        assert_eq!(game.display.pixels()[60..64], [wrapped, wrapped, wrapped, wrapped]);
+        assert_eq!(game.display.pixels()[0..5], [wrapped, wrapped, wrapped, wrapped, 0]);
+        assert_eq!(game.display.pixels().iter().filter(|pixel| **pixel != 0).count(), 8 + wrapped as usize * 24);
+        assert_eq!(game.V[0xF], 0);
+        assert_eq!(game.pc, 0x202);
+    }
+}
+
+#[test]
+// DXYN - The starting coordinate wraps around the screen.
+fn test_DXYN_start_wraps() {
+    let mut game = Chip8::with_quirks(Quirks::chip48());
+    game.memory[0x200] = 0xD0;
+    game.memory[0x201] = 0x11;
+    game.memory[0x300] = 0x80;
+    game.I = 0x300;
+    game.V[0x0] = 64 * 3 + 5;
+    game.V[0x1] = 32 * 2 + 3;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[3 * 64 + 5], 1);
+    assert_eq!(game.display.pixels().iter().filter(|pixel| **pixel != 0).count(), 1);
+}
+
+#[test]
+// EX9E - Skips the next instruction if the key stored in VX is pressed.
+fn test_EX9E() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xE1;
+    game.memory[0x201] = 0x9E;
+    game.memory[0x204] = 0xE1;
+    game.memory[0x205] = 0x9E;
+    game.V[0x1] = 0xA;
+    game.key[0xA] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x204);
+
+    game.key[0xA] = 0;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x206);
+}
+
+#[test]
+// EXA1 - Skips the next instruction if the key stored in VX isn't pressed.
+fn test_EXA1() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xE1;
+    game.memory[0x201] = 0xA1;
+    game.memory[0x204] = 0xE1;
+    game.memory[0x205] = 0xA1;
+    game.V[0x1] = 0xA;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x204);
+
+    game.key[0xA] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x206);
+}
+
 #[test]
 // FX07 - Sets VX to the value of the delay timer.
 fn test_FX07() {
@@ -604,7 +1743,7 @@ fn test_FX07() {
     game.memory[0x201] = 0x07;
     game.delay_timer = 23;
 
-    game.cycle();
+    game.cycle().unwrap();
 
     assert_eq!(game.V[0x3], 23);
     assert_eq!(game.pc, 0x202);
@@ -620,18 +1759,990 @@ fn test_FX0A() {
     game.memory[0x201] = 0x0A;
     game.delay_timer = 5;
 
-    game.cycle();
+    assert_eq!(game.cycle(), Ok(StepOutcome::WaitingForKey));
 
     assert_eq!(game.delay_timer, 5);
 
-    game.cycle();
+    game.key_down(Key::Num8);
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::WaitingForKey));
 
     assert_eq!(game.delay_timer, 5);
+    assert_eq!(game.pc, 0x200);
 
-    game.key[0x8] = 1;
+    game.key_up(Key::Num8);
 
-    game.cycle();
+    assert_eq!(game.cycle(), Ok(StepOutcome::Executed));
 
     assert_eq!(game.V[0x4], 0x8);
     assert_eq!(game.pc, 0x202);
 }
+
+#[test]
+// FX0A - Keys held down before FX0A started are ignored.
+fn test_FX0A_held_key() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF4;
+    game.memory[0x201] = 0x0A;
+    game.key_down(Key::A);
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::WaitingForKey));
+
+    game.key_up(Key::A);
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::WaitingForKey));
+
+    // A press and release in between two cycles still counts.
+    game.key_down(Key::Num3);
+    game.key_up(Key::Num3);
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::Executed));
+    assert_eq!(game.V[0x4], 0x3);
+}
+
+#[test]
+// FX15 - Sets the delay timer to VX.
+fn test_FX15() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x15;
+    game.V[0x2] = 42;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.delay_timer, 42);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// FX18 - Sets the sound timer to VX.
+fn test_FX18() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x18;
+    game.V[0x2] = 42;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.sound_timer, 42);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// FX1E - Adds VX to I. VF is not affected.
+fn test_FX1E() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x1E;
+    game.V[0x2] = 0x42;
+    game.I = 0x100;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.I, 0x142);
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// FX29 - Sets I to the location of the sprite for the character in VX.
+fn test_FX29() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x29;
+    game.V[0x2] = 0xA;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.I, 50);
+    assert_eq!(game.memory[game.I as usize..game.I as usize + 5], FONT_SET[50..55]);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// FX33 - Stores the binary-coded decimal representation of VX at I, I + 1 and I + 2.
+fn test_FX33() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x33;
+    game.V[0x2] = 254;
+    game.I = 0x300;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.memory[0x300..0x303], [2, 5, 4]);
+    assert_eq!(game.I, 0x300);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// FX55 - Stores V0 to VX (including VX) in memory starting at address I.
+fn test_FX55() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x55;
+    game.V[0x0] = 1;
+    game.V[0x1] = 2;
+    game.V[0x2] = 3;
+    game.V[0x3] = 4;
+    game.I = 0x300;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.memory[0x300..0x304], [1, 2, 3, 0]);
+    assert_eq!(game.I, 0x303);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// FX65 - Fills V0 to VX (including VX) with values from memory starting at address I.
+fn test_FX65() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x65;
+    game.memory[0x300] = 1;
+    game.memory[0x301] = 2;
+    game.memory[0x302] = 3;
+    game.memory[0x303] = 4;
+    game.I = 0x300;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x0..0x4], [1, 2, 3, 0]);
+    assert_eq!(game.I, 0x303);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+fn test_invalid_opcode() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x28;
+    game.V[0x1] = 0x42;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::InvalidOpcode { pc: 0x200, opcode: 0x8128 }));
+    assert_eq!(game.V[0x1], 0x42);
+    assert_eq!(game.pc, 0x200);
+
+    game.memory[0x200] = 0x01;
+    game.memory[0x201] = 0x23;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::InvalidOpcode { pc: 0x200, opcode: 0x0123 }));
+
+    game.memory[0x200] = 0x91;
+    game.memory[0x201] = 0x21;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::InvalidOpcode { pc: 0x200, opcode: 0x9121 }));
+}
+
+#[test]
+fn test_stack_overflow() {
+    let mut game = Chip8::new();
+    // 2200 - Calls itself forever.
+    game.memory[0x200] = 0x22;
+    game.memory[0x201] = 0x00;
+
+    for _ in 0..16 {
+        game.cycle().unwrap();
+    }
+
+    assert_eq!(game.cycle(), Err(Chip8Error::StackOverflow { pc: 0x200 }));
+    assert_eq!(game.sp, 16);
+}
+
+#[test]
+fn test_stack_underflow() {
+    let mut game = Chip8::new();
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xEE;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::StackUnderflow { pc: 0x200 }));
+    assert_eq!(game.sp, 0);
+    assert_eq!(game.pc, 0x200);
+}
+
+#[test]
+fn test_memory_fault() {
+    let mut game = Chip8::with_quirks(Quirks { display_wait: false, ..Quirks::cosmac_vip() });
+    game.memory[0x200] = 0xD0;
+    game.memory[0x201] = 0x05;
+    game.I = 0xFFE;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::MemoryFault { pc: 0x200, address: 0x1000 }));
+    assert_eq!(game.pc, 0x200);
+
+    game.memory[0x201] = 0x02;
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::Executed));
+
+    game.memory[0x202] = 0xFF;
+    game.memory[0x203] = 0x55;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::MemoryFault { pc: 0x202, address: 0x1000 }));
+
+    game.pc = 0xFFF;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::MemoryFault { pc: 0xFFF, address: 0x1000 }));
+}
+
+#[test]
+fn test_shift_quirk() {
+    let mut game = Chip8::with_quirks(Quirks::chip48());
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x26;
+    game.memory[0x202] = 0x81;
+    game.memory[0x203] = 0x2E;
+    game.V[0x1] = 0b1000_0011;
+    game.V[0x2] = 0b0100_0000;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x1], 0b0100_0001);
+    assert_eq!(game.V[0xF], 1);
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x1], 0b1000_0010);
+    assert_eq!(game.V[0xF], 0);
+    assert_eq!(game.V[0x2], 0b0100_0000);
+}
+
+#[test]
+fn test_jump_quirk() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0xB3;
+    game.memory[0x201] = 0x00;
+    game.V[0x0] = 0x42;
+    game.V[0x3] = 0x10;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x310);
+}
+
+#[test]
+fn test_load_store_quirk() {
+    let quirks = [
+        (Quirks::cosmac_vip(), 0x303),
+        (Quirks::chip48(), 0x302),
+        (Quirks::superchip(), 0x300),
+    ];
+    for &(quirks, i) in quirks.iter() {
+        let mut game = Chip8::with_quirks(quirks);
+        game.memory[0x200] = 0xF2;
+        game.memory[0x201] = 0x55;
+        game.memory[0x202] = 0xF2;
+        game.memory[0x203] = 0x65;
+        game.I = 0x300;
+
+        game.cycle().unwrap();
+
+        assert_eq!(game.I, i);
+
+        game.I = 0x300;
+        game.cycle().unwrap();
+
+        assert_eq!(game.I, i);
+    }
+}
+
+#[test]
+fn test_vf_reset_quirk() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0x81;
+    game.memory[0x201] = 0x21;
+    game.V[0xF] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0xF], 1);
+}
+
+#[test]
+fn test_clip_quirk() {
+    for &(quirks, wrapped) in [(Quirks::cosmac_vip(), 0), (Quirks::xochip(), 1)].iter() {
+        let mut game = Chip8::with_quirks(quirks);
+        game.memory[0x200] = 0xD0;
+        game.memory[0x201] = 0x12;
+        game.memory[0x300] = 0xFF;
+        game.memory[0x301] = 0xFF;
+        game.I = 0x300;
+        // The starting coordinate wraps regardless of the quirk.
+        game.V[0x0] = 64 + 60;
+        game.V[0x1] = 31;
+
+        game.cycle().unwrap();
+
+        assert_eq!(game.display.pixels()[31 * 64 + 60..], [1, 1, 1, 1]);
+        assert_eq!(game.display.pixels()[31 * 64], wrapped);
+        assert_eq!(game.display.pixels()[60], wrapped);
+        assert_eq!(game.display.pixels()[0], wrapped);
+    }
+}
+
+#[test]
+fn test_display_wait_quirk() {
+    let mut game = Chip8::with_quirks(Quirks::cosmac_vip());
+    game.memory[0x200] = 0xD0;
+    game.memory[0x201] = 0x01;
+    game.memory[0x202] = 0x61;
+    game.memory[0x203] = 0x23;
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::Executed));
+    assert_eq!(game.cycle(), Ok(StepOutcome::WaitingForVblank));
+    assert_eq!(game.cycle(), Ok(StepOutcome::WaitingForVblank));
+    assert_eq!(game.pc, 0x202);
+
+    game.tick_timers();
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::Executed));
+    assert_eq!(game.V[0x1], 0x23);
+
+    let mut game = Chip8::with_quirks(Quirks::chip48());
+    game.memory[0x200] = 0xD0;
+    game.memory[0x201] = 0x01;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.cycle(), Err(Chip8Error::InvalidOpcode { pc: 0x202, opcode: 0x0000 }));
+}
+
+#[test]
+// 00FE / 00FF - Switches between low and high resolution. (SUPER-CHIP)
+fn test_00FE_00FF() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xFF;
+    game.memory[0x202] = 0x00;
+    game.memory[0x203] = 0xFE;
+    game.display.pixels_mut()[0] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!((game.width(), game.height()), (128, 64));
+    assert_eq!(game.display.pixels().len(), 128 * 64);
+    assert!(game.display.pixels().iter().all(|pixel| *pixel == 0));
+    assert_eq!(game.pc, 0x202);
+
+    game.cycle().unwrap();
+
+    assert_eq!((game.width(), game.height()), (64, 32));
+    assert_eq!(game.display.pixels().len(), 64 * 32);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+fn test_superchip_opcodes_need_variant() {
+    let mut game = Chip8::with_quirks(Quirks::cosmac_vip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xFF;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::InvalidOpcode { pc: 0x200, opcode: 0x00FF }));
+    assert_eq!(game.width(), 64);
+}
+
+#[test]
+// 00CN - Scrolls the display down by N pixels. (SUPER-CHIP)
+fn test_00CN() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xC2;
+    game.display.pixels_mut()[3] = 1;
+    game.display.pixels_mut()[31 * 64] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[3], 0);
+    assert_eq!(game.display.pixels()[2 * 64 + 3], 1);
+    assert_eq!(game.display.pixels().iter().filter(|pixel| **pixel != 0).count(), 1);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// 00FB - Scrolls the display right by 4 pixels. (SUPER-CHIP)
+fn test_00FB() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xFB;
+    game.display.pixels_mut()[64 + 1] = 1;
+    game.display.pixels_mut()[64 + 62] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[64 + 5], 1);
+    assert_eq!(game.display.pixels().iter().filter(|pixel| **pixel != 0).count(), 1);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// 00FC - Scrolls the display left by 4 pixels. (SUPER-CHIP)
+fn test_00FC() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xFC;
+    game.display.pixels_mut()[64 + 1] = 1;
+    game.display.pixels_mut()[64 + 62] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[64 + 58], 1);
+    assert_eq!(game.display.pixels().iter().filter(|pixel| **pixel != 0).count(), 1);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// 00FD - Exits the interpreter. (SUPER-CHIP)
+fn test_00FD() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xFD;
+
+    assert_eq!(game.cycle(), Ok(StepOutcome::Exited));
+    assert!(game.has_exited());
+    assert_eq!(game.cycle(), Ok(StepOutcome::Exited));
+    assert_eq!(game.pc, 0x200);
+}
+
+#[test]
+// DXY0 - Draws a 16x16 sprite. (SUPER-CHIP)
+fn test_DXY0() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xFF;
+    game.memory[0x202] = 0xD0;
+    game.memory[0x203] = 0x10;
+    for row in 0..16 {
+        game.memory[0x300 + row * 2] = 0x80;
+        game.memory[0x300 + row * 2 + 1] = 0x01;
+    }
+    game.I = 0x300;
+    game.V[0x0] = 100;
+    game.V[0x1] = 2;
+
+    game.cycle().unwrap();
+    game.cycle().unwrap();
+
+    for row in 2..18 {
+        assert_eq!(game.display.pixels()[row * 128 + 100..row * 128 + 116],
+                   [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+    assert_eq!(game.display.pixels().iter().filter(|pixel| **pixel != 0).count(), 32);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// FX30 - Sets I to the location of the 8x10 sprite for the character in VX. (SUPER-CHIP)
+fn test_FX30() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x30;
+    game.V[0x2] = 0x9;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.memory[game.I as usize..game.I as usize + 10], BIG_FONT_SET[90..100]);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+// FX75 / FX85 - Saves and restores V0 to VX in the RPL user flags. (SUPER-CHIP)
+fn test_FX75_FX85() {
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x75;
+    game.memory[0x202] = 0xF3;
+    game.memory[0x203] = 0x85;
+    game.V[0x0] = 1;
+    game.V[0x1] = 2;
+    game.V[0x2] = 3;
+    game.V[0x3] = 4;
+
+    game.cycle().unwrap();
+
+    game.V = [0; 16];
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x0..0x4], [1, 2, 3, 0]);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+fn test_xochip_memory() {
+    let game = Chip8::with_quirks(Quirks::xochip());
+    assert_eq!(game.memory.len(), 0x10000);
+
+    let game = Chip8::with_quirks(Quirks::superchip());
+    assert_eq!(game.memory.len(), 0x1000);
+}
+
+#[test]
+// F000 NNNN - Sets I to the 16 bit address NNNN. (XO-CHIP)
+fn test_F000() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0xF0;
+    game.memory[0x201] = 0x00;
+    game.memory[0x202] = 0xAB;
+    game.memory[0x203] = 0xCD;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.I, 0xABCD);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// I and the program counter wrap around at the end of the 64K of
+// XO-CHIP memory.
+fn test_xochip_wrap() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0xF0;
+    game.memory[0x201] = 0x00;
+    game.memory[0x202] = 0xFF;
+    game.memory[0x203] = 0xFF;
+    game.memory[0x204] = 0xF0;
+    game.memory[0x205] = 0x55;
+    game.V[0x0] = 0x42;
+
+    game.cycle().unwrap();
+    game.cycle().unwrap();
+
+    assert_eq!(game.memory[0xFFFF], 0x42);
+    assert_eq!(game.I, 0);
+
+    game.memory[0xFFFE] = 0x60;
+    game.memory[0xFFFF] = 0x05;
+    game.pc = 0xFFFE;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x0], 0x05);
+    assert_eq!(game.pc, 0);
+
+    game.memory[0xFFFC] = 0xF0;
+    game.memory[0xFFFD] = 0x00;
+    game.memory[0xFFFE] = 0x12;
+    game.memory[0xFFFF] = 0x34;
+    game.pc = 0xFFFC;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.I, 0x1234);
+    assert_eq!(game.pc, 0);
+}
+
+#[test]
+// Accesses of several bytes wrap around at the end of XO-CHIP memory.
+fn test_xochip_wrap_access() {
+    let mut game = Chip8::with_quirks(Quirks { load_store: LoadStoreQuirk::Unchanged, ..Quirks::xochip() });
+    // F355, F365 with I at FFFE, then DXY2 reading the same bytes.
+    game.memory[0x200..0x206].copy_from_slice(&[0xF3, 0x55, 0xF3, 0x65, 0xD0, 0x02]);
+    game.V[0x0..0x4].copy_from_slice(&[0xF0, 0x0F, 0xAA, 0x55]);
+    game.I = 0xFFFE;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.memory[0xFFFE..], [0xF0, 0x0F]);
+    assert_eq!(game.memory[0x0..0x2], [0xAA, 0x55]);
+
+    game.V[0x0..0x4].copy_from_slice(&[0; 4]);
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x0..0x4], [0xF0, 0x0F, 0xAA, 0x55]);
+
+    game.V[0x0] = 0;
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.row(0)[..8], [1, 1, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(game.display.row(1)[..8], [0, 0, 0, 0, 1, 1, 1, 1]);
+
+    // The second word of F000 NNNN.
+    game.memory[0xFFFE] = 0xF0;
+    game.memory[0xFFFF] = 0x00;
+    game.memory[0x0000] = 0x12;
+    game.memory[0x0001] = 0x34;
+    game.pc = 0xFFFE;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.I, 0x1234);
+    assert_eq!(game.pc, 0x0002);
+}
+
+#[test]
+// Skip instructions jump over the whole F000 NNNN double word. (XO-CHIP)
+fn test_skip_F000() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0x30;
+    game.memory[0x201] = 0x00;
+    game.memory[0x202] = 0xF0;
+    game.memory[0x203] = 0x00;
+    game.memory[0x204] = 0x12;
+    game.memory[0x205] = 0x34;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x206);
+
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x30;
+    game.memory[0x201] = 0x00;
+    game.memory[0x202] = 0xF0;
+    game.memory[0x203] = 0x00;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// 5XY2 - Stores VX to VY in memory starting at address I. (XO-CHIP)
+fn test_5XY2() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0x51;
+    game.memory[0x201] = 0x32;
+    game.memory[0x202] = 0x53;
+    game.memory[0x203] = 0x12;
+    game.V[0x1] = 1;
+    game.V[0x2] = 2;
+    game.V[0x3] = 3;
+    game.I = 0x300;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.memory[0x300..0x304], [1, 2, 3, 0]);
+    assert_eq!(game.I, 0x300);
+
+    game.I = 0x310;
+    game.cycle().unwrap();
+
+    assert_eq!(game.memory[0x310..0x314], [3, 2, 1, 0]);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// 5XY3 - Fills VX to VY from memory starting at address I. (XO-CHIP)
+fn test_5XY3() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0x51;
+    game.memory[0x201] = 0x33;
+    game.memory[0x300] = 1;
+    game.memory[0x301] = 2;
+    game.memory[0x302] = 3;
+    game.I = 0x300;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.V[0x0..0x5], [0, 1, 2, 3, 0]);
+    assert_eq!(game.I, 0x300);
+    assert_eq!(game.pc, 0x202);
+
+    let mut game = Chip8::with_quirks(Quirks::superchip());
+    game.memory[0x200] = 0x51;
+    game.memory[0x201] = 0x33;
+
+    assert_eq!(game.cycle(), Err(Chip8Error::InvalidOpcode { pc: 0x200, opcode: 0x5133 }));
+}
+
+#[test]
+// FN01 - Selects the planes drawing, clearing and scrolling operate on. (XO-CHIP)
+fn test_FN01() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    // Draw a different row onto each plane.
+    game.memory[0x200] = 0xF3;
+    game.memory[0x201] = 0x01;
+    game.memory[0x202] = 0xD0;
+    game.memory[0x203] = 0x01;
+    // Then clear only the first one.
+    game.memory[0x204] = 0xF1;
+    game.memory[0x205] = 0x01;
+    game.memory[0x206] = 0x00;
+    game.memory[0x207] = 0xE0;
+    game.memory[0x300] = 0b1100_0000;
+    game.memory[0x301] = 0b1010_0000;
+    game.I = 0x300;
+
+    game.cycle().unwrap();
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[0..4], [3, 1, 2, 0]);
+
+    game.cycle().unwrap();
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[0..4], [2, 0, 2, 0]);
+    assert_eq!(game.pc, 0x208);
+}
+
+#[test]
+// F002 - Loads 16 bytes starting at I into the audio pattern buffer. (XO-CHIP)
+// FX3A - Sets the audio playback pitch to VX. (XO-CHIP)
+fn test_F002_FX3A() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0xF0;
+    game.memory[0x201] = 0x02;
+    game.memory[0x202] = 0xF1;
+    game.memory[0x203] = 0x3A;
+    for i in 0..16 {
+        game.memory[0x300 + i] = i as u8;
+    }
+    game.I = 0x300;
+    game.V[0x1] = 112;
+
+    assert_eq!(game.playback_rate(), 4000.0);
+
+    game.cycle().unwrap();
+    game.cycle().unwrap();
+
+    assert_eq!(game.audio_pattern()[..], game.memory[0x300..0x310]);
+    assert_eq!(game.playback_rate(), 8000.0);
+    assert_eq!(game.pc, 0x204);
+}
+
+#[test]
+// 00DN - Scrolls the display up by N pixels. (XO-CHIP)
+fn test_00DN() {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.memory[0x200] = 0x00;
+    game.memory[0x201] = 0xD3;
+    game.display.pixels_mut()[2 * 64 + 5] = 1;
+    game.display.pixels_mut()[3 * 64 + 5] = 1;
+
+    game.cycle().unwrap();
+
+    assert_eq!(game.display.pixels()[5], 1);
+    assert_eq!(game.display.pixels().iter().filter(|pixel| **pixel != 0).count(), 1);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+fn test_tick_timers() {
+    let mut game = Chip8::new();
+    game.delay_timer = 2;
+    game.sound_timer = 1;
+
+    game.tick_timers();
+
+    assert_eq!((game.delay_timer, game.sound_timer), (1, 0));
+
+    game.tick_timers();
+    game.tick_timers();
+
+    assert_eq!((game.delay_timer, game.sound_timer), (0, 0));
+}
+
+#[test]
+fn test_run_frame() {
+    let mut game = Chip8::with_quirks(Quirks::chip48());
+    // 7001 - Counts V0 up forever.
+    game.memory[0x200] = 0x70;
+    game.memory[0x201] = 0x01;
+    game.memory[0x202] = 0x12;
+    game.memory[0x203] = 0x00;
+    game.delay_timer = 10;
+
+    game.run_frame(10).unwrap();
+
+    assert_eq!(game.V[0x0], 5);
+    assert_eq!(game.delay_timer, 9);
+}
+
+#[test]
+fn test_run_frame_display_wait() {
+    let mut game = Chip8::with_quirks(Quirks::cosmac_vip());
+    // Draws a sprite and counts V0 up forever.
+    game.memory[0x200] = 0xD1;
+    game.memory[0x201] = 0x11;
+    game.memory[0x202] = 0x70;
+    game.memory[0x203] = 0x01;
+    game.memory[0x204] = 0x12;
+    game.memory[0x205] = 0x00;
+
+    game.run_frame(100).unwrap();
+
+    assert_eq!(game.V[0x0], 0);
+    assert_eq!(game.pc, 0x202);
+
+    game.run_frame(100).unwrap();
+
+    assert_eq!(game.V[0x0], 1);
+    assert_eq!(game.pc, 0x202);
+}
+
+#[test]
+fn test_step_for() {
+    let mut game = Chip8::with_quirks(Quirks::chip48());
+    // 7001 - Counts V0 up forever.
+    game.memory[0x200] = 0x70;
+    game.memory[0x201] = 0x01;
+    game.memory[0x202] = 0x12;
+    game.memory[0x203] = 0x00;
+    game.set_clock_speed(600);
+    game.delay_timer = 100;
+
+    game.step_for(Duration::from_millis(100)).unwrap();
+
+    assert_eq!(game.V[0x0], 30);
+    assert_eq!(game.delay_timer, 94);
+
+    // Fractions of an instruction or tick carry over.
+    for _ in 0..10 {
+        game.step_for(Duration::from_millis(1)).unwrap();
+    }
+
+    assert_eq!(game.V[0x0], 33);
+    assert_eq!(game.delay_timer, 94);
+
+    game.step_for(Duration::from_millis(7)).unwrap();
+
+    assert_eq!(game.delay_timer, 93);
+}
+
+#[test]
+fn test_keypad() {
+    let mut game = Chip8::new();
+
+    game.key_down(Key::A);
+    game.key_down(Key::Num1);
+
+    assert!(game.is_key_down(Key::A));
+    assert_eq!(game.keypad(), 0b0000_0100_0000_0010);
+
+    game.key_up(Key::A);
+    game.set_keypad(0b1000_0000_0000_0001 | game.keypad());
+
+    assert!(!game.is_key_down(Key::A));
+    assert_eq!(game.keypad(), 0b1000_0000_0000_0011);
+    assert_eq!(Key::from_index(0xF), Some(Key::F));
+    assert_eq!(Key::from_index(0x10), None);
+}
+
+#[cfg(test)]
+struct RecordingSink(std::rc::Rc<std::cell::RefCell<Vec<bool>>>);
+
+#[cfg(test)]
+impl AudioSink for RecordingSink {
+    fn start_tone(&mut self) {
+        self.0.borrow_mut().push(true);
+    }
+
+    fn stop_tone(&mut self) {
+        self.0.borrow_mut().push(false);
+    }
+}
+
+#[test]
+fn test_audio_sink() {
+    let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut game = Chip8::new();
+    game.set_audio_sink(Box::new(RecordingSink(events.clone())));
+    // FX18 - Sets the sound timer to 2, then to 1 and then to 0.
+    game.memory[0x200] = 0xF2;
+    game.memory[0x201] = 0x18;
+    game.memory[0x202] = 0xF1;
+    game.memory[0x203] = 0x18;
+    game.memory[0x204] = 0xF0;
+    game.memory[0x205] = 0x18;
+    game.V[0x1] = 1;
+    game.V[0x2] = 2;
+
+    assert!(!game.is_beeping());
+
+    game.cycle().unwrap();
+    game.cycle().unwrap();
+
+    assert!(game.is_beeping());
+    assert_eq!(*events.borrow(), [true]);
+
+    game.tick_timers();
+
+    assert!(!game.is_beeping());
+    assert_eq!(*events.borrow(), [true, false]);
+
+    game.cycle().unwrap();
+
+    assert_eq!(*events.borrow(), [true, false]);
+}
+
+#[test]
+fn test_write_pbm() {
+    let mut game = Chip8::new();
+    game.display.pixels_mut()[1] = 1;
+    game.display.pixels_mut()[64 + 63] = 3;
+    let mut out = Vec::new();
+
+    game.write_pbm(&mut out).unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2 + 32);
+    assert_eq!(lines[0], "P1");
+    assert_eq!(lines[1], "64 32");
+    assert!(lines[2].starts_with("0 1 0 0"));
+    assert!(lines[3].ends_with("0 0 1"));
+    assert_eq!(lines[4], vec!["0"; 64].join(" "));
+}
+
+#[test]
+fn test_set_seed() {
+    let mut first = Chip8::new();
+    let mut second = Chip8::new();
+    for game in [&mut first, &mut second].iter_mut() {
+        game.set_seed(1234);
+        for address in (0x200..0x220).step_by(2) {
+            game.memory[address] = 0xC0 + (address as u8 / 2 % 16);
+            game.memory[address + 1] = 0xFF;
+        }
+        game.run_frame(16).unwrap();
+    }
+
+    assert_eq!(first.seed(), 1234);
+    assert_eq!(first.V, second.V);
+    assert!(first.V.iter().any(|value| *value != first.V[0]));
+}
+
+#[test]
+fn test_load_rom() {
+    let mut game = Chip8::new();
+    let rom = vec![0xAB; MEMORY_SIZE - 0x200];
+    game.load_rom(&rom).unwrap();
+    assert_eq!(game.memory[0xFFF], 0xAB);
+
+    match game.load_rom(&[0; MEMORY_SIZE - 0x1FF]) {
+        Err(LoadError::TooBig { size: 0xE01, available: 0xE00 }) => {},
+        result => panic!("expected the ROM to be too big, got {:?}", result),
+    }
+    assert_eq!(game.memory[0x200], 0xAB);
+
+    // XO-CHIP has more memory, and the ETI-660 loads ROMs further up.
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    game.set_load_address(ETI660_LOAD_ADDRESS);
+    game.load_rom(&[0; MEMORY_SIZE]).unwrap();
+    assert_eq!(game.pc(), 0x600);
+
+    // A single read of a chain only returns its first part.
+    let mut game = Chip8::new();
+    game.load(&mut (&[0x12, 0x34][..]).chain(&[0x56][..])).unwrap();
+    assert_eq!(game.memory[0x200..0x204], [0x12, 0x34, 0x56, 0x00]);
+}
+
+#[test]
+fn test_display_dirty() {
+    let mut game = Chip8::new();
+    // Draws the font sprite for 0 at 10,5 and clears the screen.
+    let program = [0x6A, 0x0A, 0x6B, 0x05, 0xA0, 0x00, 0xDA, 0xB5, 0x00, 0xE0];
+    game.memory[0x200..0x20A].copy_from_slice(&program);
+    assert_eq!(game.display_mut().take_dirty(), Some(Region { x: 0, y: 0, width: 64, height: 32 }));
+
+    for _ in 0..3 {
+        game.cycle().unwrap();
+    }
+    assert_eq!(game.display_mut().take_dirty(), None);
+    game.cycle().unwrap();
+    assert_eq!(game.display_mut().take_dirty(), Some(Region { x: 10, y: 5, width: 4, height: 5 }));
+    assert_eq!(game.display().pixel(10, 5), 1);
+    // Past the display wait.
+    game.tick_timers();
+    game.cycle().unwrap();
+    assert_eq!(game.display_mut().take_dirty(), Some(Region { x: 0, y: 0, width: 64, height: 32 }));
+}
@@ -1,80 +1,62 @@
 //#![deny(warnings)]
 
-#![feature(link_args)]
-
-#[link_args = "-s USE_SDL=2 -s ASYNCIFY=1"]
-extern "C" {
-    pub fn emscripten_sleep(dur: c_int);
-}
-
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 extern crate chip8;
 
-use std::os::raw::c_int;
-use std::io::Cursor;
-use sdl2::rect::Rect;
-use sdl2::pixels::Color;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use sdl2::EventPump;
-use sdl2::keyboard::Scancode;
-use chip8::Chip8;
+mod cli;
 
-fn set_keys(event_pump: &mut EventPump) {
-    let is_pressed = event_pump.keyboard_state().is_scancode_pressed(Scancode::A);
-}
+use std::env;
+use std::process;
 
-fn draw(canvas: &mut Canvas<Window>, pixels: &[u8; 2048]) {
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
+const USAGE: &str = "Usage:
+    chip8 [play] [--scale N] [--hz N] [--quirks PRESET] [--database FILE] [--tone HZ]
+                 [--volume 0-1] [--rewind SECONDS] [--record MOVIE | --replay MOVIE] ROM
+    chip8 term [--hz N] [--quirks PRESET] [--database FILE] [--render half|braille]
+               [--hold FRAMES] ROM
+    chip8 headless [--frames N] [--hz N] [--quirks PRESET] [--database FILE] [--seed N] [--keys SCRIPT]
+                   [--screenshot FILE] [--dump FILE] [--record MOVIE | --replay MOVIE]
+                   [--trace FILE] [--trace-range START-END] [--trace-limit LINES] ROM
+    chip8 disasm [--syntax classic|octo] [--quirks PRESET] [--base ADDRESS] ROM
+    chip8 asm [--output ROM] [--symbols FILE] SOURCE
+    chip8 debug [--quirks PRESET] [--load-address ADDRESS] ROM
 
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    for (i, pixel) in pixels.iter().enumerate() {
-        let y = i / 64;
-        let x = i - 64 * y;
-        if *pixel != 0 {
-            canvas.fill_rect(Rect::new(x as i32, y as i32, 1, 1)).unwrap();
-        }
-    }
+Quirks presets: vip (default), chip48, schip, xochip";
 
-    canvas.present();
+#[cfg(feature = "sdl")]
+fn play(args: Vec<String>) -> Result<(), String> {
+    cli::sdl::run(args)
 }
 
-fn main() {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    let window = video_subsystem.window("Example", 64, 32).build().unwrap();
-
-    let mut game = Chip8::new();
-
-    let mut buff = Cursor::new(vec![
-                               0xA2,
-                               0x06,
-                               0xD0,
-                               0x0A,
-                               0xF1,
-                               0x0A,
-
-        0xF0, 0x90, 0x90, 0x90, 0xF0,
-        0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    ]);
+#[cfg(not(feature = "sdl"))]
+fn play(_args: Vec<String>) -> Result<(), String> {
+    Err("built without the sdl feature, try `chip8 term` or `chip8 headless`".to_string())
+}
 
-    //let mut buff = Cursor::new(vec![0xF1, 0x0A]);
-    game.load(&mut buff);
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
 
-    // Let's create a Canvas which we will use to draw in our Window
-    let mut canvas: Canvas<Window> = window.into_canvas()
-        .present_vsync() //< this means the screen cannot
-        // render faster than your display rate (usually 60Hz or 144Hz)
-        .build().unwrap();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
 
-    loop {
-        game.cycle();
-        draw(&mut canvas, &game.gfx);
-        set_keys(&mut event_pump);
-        unsafe {
-            emscripten_sleep(1000 / 60);
-        }
+    let command = match args[0].as_str() {
+        "play" | "term" | "headless" | "disasm" | "asm" | "debug" => args.remove(0),
+        _ => "play".to_string(),
+    };
+
+    let result = match command.as_str() {
+        "term" => cli::term::run(args),
+        "headless" => cli::headless::run(args),
+        "disasm" => cli::disasm::run(args),
+        "asm" => cli::asm::run(args),
+        "debug" => cli::debug::run(args),
+        _ => play(args),
+    };
+
+    if let Err(err) = result {
+        eprintln!("chip8: {}", err);
+        process::exit(1);
     }
 }
@@ -0,0 +1,286 @@
+//! Movies, which record the keypad state of every frame of a run so
+//! that it can be replayed exactly.
+//!
+//! A replay starts from a freshly loaded ROM with the same quirks and
+//! random seed as the recording and runs the same number of
+//! instructions each frame, so it can only go out of sync if the
+//! emulator itself changed. Checkpoints with screen hashes catch that.
+//!
+//! Movie files use the conventions of save states. Version 1 is laid
+//! out as
+//!
+//! ```text
+//! magic          4 bytes  "C8MV"
+//! version        u16
+//! quirks         7 bytes  as in save states
+//! seed           u32
+//! instructions   u32      per frame, at least 1
+//! ROM checksum   u32      CRC-32 of the ROM
+//! frames         u32
+//! keypad         u16 for every frame
+//! checkpoints    u32
+//! frame          u32      for every checkpoint, at most `frames`
+//! screen hash    u64
+//! checksum       u32      CRC-32 of everything before it
+//! ```
+
+use error::{Chip8Error, LoadError, MovieError, StateError};
+use quirks::Quirks;
+use state::{crc32, read_quirks, write_quirks, Reader, Writer};
+use Chip8;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+
+/// The format version written by `Movie::to_bytes`.
+pub const MOVIE_VERSION: u16 = 1;
+
+/// The screen hash expected after a number of frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Number of frames run before the screen was hashed.
+    pub frame: u32,
+    pub screen_hash: u64,
+}
+
+/// A recorded run of a ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub quirks: Quirks,
+    /// The seed of the random number generator.
+    pub seed: u32,
+    pub instructions_per_frame: u32,
+    /// CRC-32 of the ROM the movie was recorded with.
+    pub rom_checksum: u32,
+    /// The keypad state of every frame, as passed to `set_keypad`.
+    pub frames: Vec<u16>,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl Movie {
+    /// Creates an empty movie of `rom`.
+    pub fn new(rom: &[u8], quirks: Quirks, seed: u32, instructions_per_frame: u32) -> Movie {
+        Movie {
+            quirks,
+            seed,
+            instructions_per_frame: instructions_per_frame.max(1),
+            rom_checksum: crc32(rom),
+            frames: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Creates the machine that recording or replaying the movie
+    /// starts with.
+    pub fn start(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        if crc32(rom) != self.rom_checksum {
+            return Err(MovieError::RomMismatch);
+        }
+
+        let mut game = Chip8::with_quirks(self.quirks);
+        game.set_seed(self.seed);
+        match game.load_rom(rom) {
+            Ok(()) => Ok(game),
+            Err(LoadError::TooBig { size, available }) => Err(MovieError::RomTooBig { size, available }),
+            Err(LoadError::Io(_)) => unreachable!("no I/O when loading from memory"),
+        }
+    }
+
+    /// Runs a frame of `game` and appends its keypad state to the
+    /// movie.
+    ///
+    /// Keys that were pressed and released again since the last frame
+    /// are held down for the whole frame, as a replay only sees the
+    /// state of each frame.
+    pub fn record_frame(&mut self, game: &mut Chip8) -> Result<(), Chip8Error> {
+        let held = game.keypad();
+        let keys = game.take_recent_keys();
+        game.set_keypad(keys);
+        let result = game.run_frame(self.instructions_per_frame);
+        game.set_keypad(held);
+        // Only keys pressed from now on count for the next frame.
+        game.take_recent_keys();
+        result?;
+        self.frames.push(keys);
+        Ok(())
+    }
+
+    /// Records the current screen of `game` as a checkpoint after the
+    /// frames recorded so far.
+    pub fn add_checkpoint(&mut self, game: &Chip8) {
+        let frame = self.frames.len() as u32;
+        self.checkpoints.retain(|checkpoint| checkpoint.frame != frame);
+        self.checkpoints.push(Checkpoint { frame, screen_hash: game.screen_hash() });
+    }
+
+    /// Runs frame number `frame` of the movie on `game`, which has to
+    /// have run the frames before it, and verifies the screen if there
+    /// is a checkpoint after it.
+    pub fn play_frame(&self, game: &mut Chip8, frame: usize) -> Result<(), MovieError> {
+        game.set_keypad(self.frames[frame]);
+        game.run_frame(self.instructions_per_frame)?;
+        self.verify(game, frame as u32 + 1)
+    }
+
+    fn verify(&self, game: &Chip8, frame: u32) -> Result<(), MovieError> {
+        for checkpoint in self.checkpoints.iter().filter(|checkpoint| checkpoint.frame == frame) {
+            let actual = game.screen_hash();
+            if actual != checkpoint.screen_hash {
+                return Err(MovieError::Desync { frame, expected: checkpoint.screen_hash, actual });
+            }
+        }
+        Ok(())
+    }
+
+    /// Replays the whole movie and returns the machine in its final
+    /// state. Stops early if the program exits.
+    pub fn replay(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let mut game = self.start(rom)?;
+        self.verify(&game, 0)?;
+        for frame in 0..self.frames.len() {
+            if game.has_exited() {
+                break;
+            }
+            self.play_frame(&mut game, frame)?;
+        }
+        Ok(game)
+    }
+
+    /// Serializes the movie in the format described in the `movie`
+    /// module.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Writer::new(MAGIC, MOVIE_VERSION, self.frames.len() * 2 + self.checkpoints.len() * 12 + 64);
+        write_quirks(&mut out, &self.quirks);
+        out.u32(self.seed);
+        out.u32(self.instructions_per_frame);
+        out.u32(self.rom_checksum);
+
+        out.u32(self.frames.len() as u32);
+        for keys in self.frames.iter() {
+            out.u16(*keys);
+        }
+        out.u32(self.checkpoints.len() as u32);
+        for checkpoint in self.checkpoints.iter() {
+            out.u32(checkpoint.frame);
+            out.u64(checkpoint.screen_hash);
+        }
+        out.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        let (mut input, _) = Reader::new(data, MAGIC, MOVIE_VERSION)?;
+        let quirks = read_quirks(&mut input)?;
+        let seed = input.u32()?;
+        let instructions_per_frame = input.u32()?;
+        if instructions_per_frame == 0 {
+            return Err(StateError::Corrupt.into());
+        }
+        let rom_checksum = input.u32()?;
+
+        // The counts aren't trusted for preallocating, as a corrupt
+        // one could be huge.
+        let mut frames = Vec::new();
+        for _ in 0..input.u32()? {
+            frames.push(input.u16()?);
+        }
+        let mut checkpoints = Vec::new();
+        for _ in 0..input.u32()? {
+            let checkpoint = Checkpoint { frame: input.u32()?, screen_hash: input.u64()? };
+            if checkpoint.frame as usize > frames.len() {
+                return Err(StateError::Corrupt.into());
+            }
+            checkpoints.push(checkpoint);
+        }
+        input.finish()?;
+
+        Ok(Movie { quirks, seed, instructions_per_frame, rom_checksum, frames, checkpoints })
+    }
+}
+
+/// Draws a random sprite at a random position each frame, unless
+/// key 5 is held.
+#[cfg(test)]
+const RANDOM_SPRITES: [u8; 16] = [
+    0x60, 0x05, 0xE0, 0xA1, 0x12, 0x00, 0xC1, 0x3F, 0xC2, 0x1F, 0xF1, 0x29, 0xD1, 0x25, 0x12, 0x00,
+];
+
+#[cfg(test)]
+fn record_random_sprites() -> Movie {
+    let mut movie = Movie::new(&RANDOM_SPRITES, Quirks::chip48(), 42, 10);
+    let mut game = movie.start(&RANDOM_SPRITES).unwrap();
+    for frame in 0..30 {
+        game.set_keypad(if frame % 10 < 5 { 1 << 5 } else { 0 });
+        movie.record_frame(&mut game).unwrap();
+        if frame % 10 == 9 {
+            movie.add_checkpoint(&game);
+        }
+    }
+    movie
+}
+
+#[test]
+fn test_replay() {
+    let movie = record_random_sprites();
+    assert_eq!(movie.frames.len(), 30);
+    assert_eq!(movie.checkpoints.len(), 3);
+
+    let mut game = movie.start(&RANDOM_SPRITES).unwrap();
+    for frame in 0..30 {
+        movie.play_frame(&mut game, frame).unwrap();
+    }
+    assert_eq!(game.screen_hash(), movie.checkpoints[2].screen_hash);
+    assert!(game.display().pixels().iter().any(|pixel| *pixel != 0));
+
+    assert_eq!(movie.replay(&RANDOM_SPRITES).unwrap().screen_hash(), game.screen_hash());
+}
+
+#[test]
+fn test_record_tap() {
+    use keypad::Key;
+
+    // F00A, 6101, 1204 - Waits for a key, then sets V1 to 1.
+    let rom = [0xF0, 0x0A, 0x61, 0x01, 0x12, 0x04];
+    let mut movie = Movie::new(&rom, Quirks::chip48(), 1, 10);
+    let mut game = movie.start(&rom).unwrap();
+    movie.record_frame(&mut game).unwrap();
+    game.key_down(Key::Num5);
+    game.key_up(Key::Num5);
+    for _ in 0..3 {
+        movie.record_frame(&mut game).unwrap();
+    }
+
+    assert_eq!(movie.frames, [0, 1 << 5, 0, 0]);
+    assert_eq!(game.keypad(), 0);
+    let replay = movie.replay(&rom).unwrap();
+    assert_eq!(replay.v()[0x0..0x2], [0x5, 0x1]);
+    assert_eq!(replay.v(), game.v());
+}
+
+#[test]
+fn test_replay_errors() {
+    let mut movie = record_random_sprites();
+    assert_eq!(movie.replay(&RANDOM_SPRITES[..12]).err(), Some(MovieError::RomMismatch));
+
+    // Different random numbers draw different sprites.
+    movie.seed = 43;
+    match movie.replay(&RANDOM_SPRITES).err() {
+        Some(MovieError::Desync { frame: 10, expected, actual }) => {
+            assert_eq!(expected, movie.checkpoints[0].screen_hash);
+            assert!(actual != expected);
+        },
+        err => panic!("expected a desync, got {:?}", err),
+    }
+}
+
+#[test]
+fn test_movie_bytes() {
+    let movie = record_random_sprites();
+    let bytes = movie.to_bytes();
+
+    assert_eq!(Movie::from_bytes(&bytes), Ok(movie));
+    assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Format(StateError::Truncated)));
+    assert_eq!(Movie::from_bytes(b"C8ST\x01\x00"), Err(MovieError::Format(StateError::InvalidMagic)));
+
+    let mut corrupt = bytes.clone();
+    corrupt[30] ^= 0x20;
+    assert_eq!(Movie::from_bytes(&corrupt), Err(MovieError::Format(StateError::Corrupt)));
+}
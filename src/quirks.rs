@@ -0,0 +1,132 @@
+/// Instruction set understood by the interpreter. Each variant is a
+/// superset of the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant {
+    /// The original CHIP-8 instruction set.
+    Chip8,
+    /// CHIP-8 plus the SUPER-CHIP 1.1 extensions: hi-res mode,
+    /// scrolling, 16x16 sprites, the big font and the RPL flags.
+    SuperChip,
+    /// SUPER-CHIP plus the XO-CHIP extensions: 64KB of memory, two
+    /// bitplanes, the audio pattern buffer and register range
+    /// loads and stores.
+    XoChip,
+}
+
+/// How FX55 and FX65 change I after storing or loading registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStoreQuirk {
+    /// I is increased by X + 1, as on the COSMAC VIP.
+    IncrementByXPlusOne,
+    /// I is increased by X, as on the HP48 interpreters.
+    IncrementByX,
+    /// I is left unchanged, as in SUPER-CHIP 1.1.
+    Unchanged,
+}
+
+/// Behaviour of the instructions that the various CHIP-8
+/// interpreters disagree on.
+///
+/// Use one of the presets to match a platform, or tweak single
+/// fields for ROMs that depend on an unusual combination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// Instruction set extensions decoded in addition to the original
+    /// CHIP-8 instructions.
+    pub variant: Variant,
+    /// 8XY6 and 8XYE shift VY and store the result in VX. When unset,
+    /// VX is shifted in place and VY is ignored.
+    pub shift_uses_vy: bool,
+    /// How FX55 and FX65 change I.
+    pub load_store: LoadStoreQuirk,
+    /// BXNN jumps to XNN plus VX instead of NNN plus V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the edges of the screen instead of
+    /// wrapping around to the opposite side.
+    pub clip_sprites: bool,
+    /// DXYN waits for the next vertical blank before execution
+    /// continues, limiting drawing to one sprite per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original interpreter on the COSMAC VIP.
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            variant: Variant::Chip8,
+            shift_uses_vy: true,
+            load_store: LoadStoreQuirk::IncrementByXPlusOne,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    /// CHIP-48 on the HP48 calculators.
+    pub fn chip48() -> Quirks {
+        Quirks {
+            variant: Variant::Chip8,
+            shift_uses_vy: false,
+            load_store: LoadStoreQuirk::IncrementByX,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1.
+    pub fn superchip() -> Quirks {
+        Quirks {
+            variant: Variant::SuperChip,
+            shift_uses_vy: false,
+            load_store: LoadStoreQuirk::Unchanged,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// Looks up a preset by its short name: `vip`, `chip48`, `schip`
+    /// or `xochip`.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::cosmac_vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "schip" => Some(Quirks::superchip()),
+            "xochip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Quirks {
+        Quirks {
+            variant: Variant::XoChip,
+            shift_uses_vy: true,
+            load_store: LoadStoreQuirk::IncrementByXPlusOne,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::cosmac_vip()
+    }
+}
+
+#[test]
+fn test_preset() {
+    assert_eq!(Quirks::preset("vip"), Some(Quirks::cosmac_vip()));
+    assert_eq!(Quirks::preset("schip"), Some(Quirks::superchip()));
+    assert_eq!(Quirks::preset("xochip").map(|quirks| quirks.variant), Some(Variant::XoChip));
+    assert_eq!(Quirks::preset("chip-8"), None);
+}
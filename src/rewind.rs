@@ -0,0 +1,219 @@
+//! Rewinding, which keeps a bounded history of save states, one per
+//! frame.
+//!
+//! Only the newest state is kept in full. Each older one is stored as
+//! the XOR of itself with its successor, which is mostly zeros as only
+//! a few bytes change from one frame to the next, and run-length
+//! encoded as pairs of a run of zeros and a run of literal bytes.
+
+use std::collections::VecDeque;
+use Chip8;
+
+/// The difference between two consecutive save states.
+struct Delta {
+    /// Length of the older state, which differs from the newer one
+    /// when the screen resolution changed in between.
+    len: usize,
+    /// Run-length encoded XOR of the two states.
+    runs: Vec<u8>,
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= (byte as usize & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+impl Delta {
+    /// Encodes the changes that turn `newer` back into `older`.
+    fn between(older: &[u8], newer: &[u8]) -> Delta {
+        let len = older.len().max(newer.len());
+        let byte = |i: usize| older.get(i).cloned().unwrap_or(0) ^ newer.get(i).cloned().unwrap_or(0);
+
+        let mut runs = Vec::new();
+        let mut i = 0;
+        while i < len {
+            let zeros = (i..len).take_while(|&j| byte(j) == 0).count();
+            i += zeros;
+            let literals = (i..len).take_while(|&j| byte(j) != 0).count();
+            write_varint(&mut runs, zeros);
+            write_varint(&mut runs, literals);
+            runs.extend((i..i + literals).map(byte));
+            i += literals;
+        }
+
+        Delta { len: older.len(), runs }
+    }
+
+    /// Turns `state` into the older state this delta was made from.
+    fn apply(&self, state: &mut Vec<u8>) {
+        if state.len() < self.len {
+            state.resize(self.len, 0);
+        }
+
+        let mut i = 0;
+        let mut position = 0;
+        while position < self.runs.len() {
+            i += read_varint(&self.runs, &mut position);
+            let literals = read_varint(&self.runs, &mut position);
+            for byte in &self.runs[position..position + literals] {
+                state[i] ^= byte;
+                i += 1;
+            }
+            position += literals;
+        }
+
+        state.truncate(self.len);
+    }
+}
+
+/// The save states of the most recent frames.
+pub struct History {
+    capacity: usize,
+    latest: Vec<u8>,
+    /// Deltas leading from `latest` back in time, the oldest first.
+    deltas: VecDeque<Delta>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            latest: Vec::new(),
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Number of frames that can currently be rewound.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    fn push(&mut self, state: Vec<u8>) {
+        if !self.latest.is_empty() {
+            self.deltas.push_back(Delta::between(&self.latest, &state));
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = state;
+    }
+
+    /// Removes the newest `frames` states and returns the one before
+    /// them.
+    fn pop(&mut self, frames: usize) -> Vec<u8> {
+        for _ in 0..frames {
+            let delta = self.deltas.pop_back().unwrap();
+            delta.apply(&mut self.latest);
+        }
+        self.latest.clone()
+    }
+}
+
+impl Chip8 {
+    /// Keeps the state of the last `frames` frames, taken whenever the
+    /// timers tick, so that they can be returned to with `rewind`.
+    /// Passing 0 disables rewinding and drops the history.
+    pub fn enable_rewind(&mut self, frames: usize) {
+        self.history = if frames > 0 { Some(History::new(frames)) } else { None };
+    }
+
+    /// Number of frames that `rewind` can currently go back.
+    pub fn rewind_frames(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Records the current state in the rewind history, if enabled.
+    pub(crate) fn record_frame(&mut self) {
+        if let Some(mut history) = self.history.take() {
+            history.push(self.save_state());
+            self.history = Some(history);
+        }
+    }
+
+    /// Returns to the state `frames` frames before the last timer
+    /// tick, or as far back as the history goes. Returns the number of
+    /// frames actually rewound.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let mut history = match self.history.take() {
+            Some(history) => history,
+            None => return 0,
+        };
+
+        let frames = frames.min(history.len());
+        let state = history.pop(frames);
+        if !state.is_empty() {
+            self.load_state(&state).expect("rewind history holds valid states");
+        }
+
+        self.history = Some(history);
+        frames
+    }
+}
+
+#[test]
+fn test_delta() {
+    let older = vec![1, 2, 3, 0, 0, 0, 0, 9];
+    let newer = vec![1, 2, 4, 0, 0, 0, 0, 9, 7, 7];
+    let delta = Delta::between(&older, &newer);
+
+    // Two unchanged bytes, one changed one, four unchanged ones and
+    // the two bytes that were added.
+    assert_eq!(delta.runs, [2, 1, 3 ^ 4, 5, 2, 7, 7]);
+
+    let mut state = newer.clone();
+    delta.apply(&mut state);
+    assert_eq!(state, older);
+
+    let mut state = older.clone();
+    Delta::between(&newer, &older).apply(&mut state);
+    assert_eq!(state, newer);
+}
+
+#[test]
+fn test_rewind() {
+    let mut game = Chip8::new();
+    // Counts up V0 once per frame.
+    let program = [0x70, 0x01, 0x12, 0x00];
+    game.memory[0x200..0x204].copy_from_slice(&program);
+    game.enable_rewind(10);
+
+    for _ in 0..15 {
+        game.run_frame(2).unwrap();
+    }
+    // Only the last 10 frames are kept.
+    assert_eq!(game.v()[0], 15);
+    assert_eq!(game.rewind_frames(), 10);
+
+    assert_eq!(game.rewind(3), 3);
+    assert_eq!(game.v()[0], 12);
+    assert_eq!(game.rewind_frames(), 7);
+
+    game.run_frame(2).unwrap();
+    assert_eq!(game.v()[0], 13);
+    assert_eq!(game.rewind_frames(), 8);
+
+    assert_eq!(game.rewind(100), 8);
+    assert_eq!(game.v()[0], 5);
+    assert_eq!(game.rewind(1), 0);
+
+    game.enable_rewind(0);
+    game.run_frame(2).unwrap();
+    assert_eq!(game.rewind(1), 0);
+}
@@ -0,0 +1,67 @@
+/// A source of random numbers for CXNN.
+///
+/// The whole state has to fit in 64 bits, so that it can be part of
+/// save states and runs with the same seed and inputs stay identical
+/// after saving and loading.
+pub trait Rng {
+    /// Restarts the sequence from `seed`.
+    fn seed(&mut self, seed: u32);
+    /// Returns the next random byte.
+    fn next_byte(&mut self) -> u8;
+    /// The current position in the sequence.
+    fn state(&self) -> u64;
+    /// Returns to a position previously given by `state`.
+    fn set_state(&mut self, state: u64);
+}
+
+/// The default generator, Marsaglia's 32 bit xorshift.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xorshift {
+    state: u32,
+}
+
+impl Xorshift {
+    pub fn new(seed: u32) -> Xorshift {
+        let mut rng = Xorshift { state: 1 };
+        rng.seed(seed);
+        rng
+    }
+}
+
+impl Rng for Xorshift {
+    /// Xorshift never leaves the all-zero state, so 0 is treated like 1.
+    fn seed(&mut self, seed: u32) {
+        self.state = seed.max(1);
+    }
+
+    /// Advances the generator and returns the lowest byte.
+    fn next_byte(&mut self) -> u8 {
+        let mut r = self.state;
+        r ^= r << 13;
+        r ^= r >> 17;
+        r ^= r << 5;
+        self.state = r;
+        r as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = (state as u32).max(1);
+    }
+}
+
+#[test]
+fn test_xorshift() {
+    let mut rng = Xorshift::new(0);
+    assert_eq!(rng, Xorshift::new(1));
+    // 1 becomes 0x00042021 after one step.
+    assert_eq!(rng.next_byte(), 0x21);
+
+    let state = rng.state();
+    let bytes: Vec<u8> = (0..8).map(|_| rng.next_byte()).collect();
+    rng.set_state(state);
+    assert_eq!((0..8).map(|_| rng.next_byte()).collect::<Vec<u8>>(), bytes);
+}
@@ -0,0 +1,326 @@
+//! Settings for known ROMs, looked up by the SHA-1 hash of the ROM.
+//!
+//! The database uses the layout of `programs.json` from the community
+//! CHIP-8 database: an array of programs, each with a `title` and its
+//! ROMs keyed by their hash.
+//!
+//! ```json
+//! [{
+//!     "title": "Example",
+//!     "roms": {
+//!         "<sha1>": {
+//!             "platforms": ["superchip", "xochip"],
+//!             "quirkyPlatforms": { "superchip": { "shift": false } },
+//!             "tickrate": 30,
+//!             "colors": { "pixels": ["#000000", "#FFFFFF"] },
+//!             "keys": { "up": 5, "down": 8, "a": 6 }
+//!         }
+//!     }
+//! }]
+//! ```
+//!
+//! All other fields of the database, like authors or descriptions, are
+//! ignored.
+
+use std::collections::HashMap;
+use error::DatabaseError;
+use json::Json;
+use sha1::sha1_hex;
+use {Key, LoadStoreQuirk, Quirks, Variant, TIMER_FREQUENCY};
+
+/// The database built into the emulator.
+const EMBEDDED: &str = include_str!("programs.json");
+
+/// What the database knows about one ROM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    /// Title of the program the ROM belongs to.
+    pub title: String,
+    /// Platforms the ROM runs on, best first, as named in the database,
+    /// e.g. `originalChip8`, `superchip` or `xochip`.
+    pub platforms: Vec<String>,
+    /// Quirks for the first platform the emulator supports, including
+    /// any deviations the ROM needs on it. `None` if the emulator
+    /// supports none of the platforms.
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame.
+    pub tickrate: Option<u32>,
+    /// Colours of the pixels as `(r, g, b)`, starting with the
+    /// background, in the order of the XO-CHIP plane combinations.
+    pub colors: Vec<(u8, u8, u8)>,
+    /// The keypad keys the ROM uses for inputs like `up`, `down`,
+    /// `left`, `right`, `a` and `b`.
+    pub keys: Vec<(String, Key)>,
+}
+
+impl RomInfo {
+    /// Instructions per second at the ROM's tickrate.
+    pub fn clock_speed(&self) -> Option<u32> {
+        self.tickrate.map(|tickrate| tickrate * TIMER_FREQUENCY)
+    }
+
+    /// The key the ROM uses for input `name`, e.g. `up`.
+    pub fn key(&self, name: &str) -> Option<Key> {
+        self.keys.iter().find(|key| key.0 == name).map(|key| key.1)
+    }
+}
+
+/// ROM settings by SHA-1 hash.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    /// An empty database.
+    pub fn new() -> RomDatabase {
+        RomDatabase::default()
+    }
+
+    /// The database built into the emulator.
+    pub fn embedded() -> RomDatabase {
+        RomDatabase::parse(EMBEDDED).expect("the embedded ROM database is invalid")
+    }
+
+    /// Reads a database in the format of `programs.json`.
+    pub fn parse(json: &str) -> Result<RomDatabase, DatabaseError> {
+        let json = Json::parse(json)?;
+        let programs = json.as_array().ok_or_else(|| format_error("the database is not an array of programs"))?;
+
+        let mut database = RomDatabase::new();
+        for (index, program) in programs.iter().enumerate() {
+            let title = program.get("title").and_then(Json::as_str)
+                .ok_or_else(|| format_error(&format!("program {} has no title", index)))?;
+            let roms = program.get("roms").and_then(Json::as_object)
+                .ok_or_else(|| format_error(&format!("{} has no roms", title)))?;
+            for (hash, rom) in roms {
+                let info = parse_rom(title, rom)
+                    .map_err(|err| format_error(&format!("{}, ROM {}: {}", title, hash, err)))?;
+                database.roms.insert(hash.to_lowercase(), info);
+            }
+        }
+        Ok(database)
+    }
+
+    /// Adds all ROMs of `other`, replacing those known to both.
+    pub fn merge(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    /// Looks up `rom` by its hash.
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.get(&sha1_hex(rom))
+    }
+
+    /// Looks up a ROM by its SHA-1 hash in hex digits.
+    pub fn get(&self, sha1: &str) -> Option<&RomInfo> {
+        self.roms.get(&sha1.to_lowercase())
+    }
+
+    /// Number of ROMs in the database.
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+fn format_error(message: &str) -> DatabaseError {
+    DatabaseError::Format(message.to_string())
+}
+
+fn parse_rom(title: &str, rom: &Json) -> Result<RomInfo, String> {
+    let mut platforms = Vec::new();
+    for platform in rom.get("platforms").and_then(Json::as_array).ok_or("no platforms")? {
+        platforms.push(platform.as_str().ok_or("platforms are not names")?.to_string());
+    }
+
+    let mut quirks = None;
+    if let Some(platform) = platforms.iter().find(|platform| platform_quirks(platform).is_some()) {
+        let mut rom_quirks = platform_quirks(platform).unwrap();
+        if let Some(quirky) = rom.get("quirkyPlatforms").and_then(|quirky| quirky.get(platform)) {
+            apply_quirks(&mut rom_quirks, quirky)?;
+        }
+        quirks = Some(rom_quirks);
+    }
+
+    let tickrate = match rom.get("tickrate") {
+        Some(tickrate) => Some(tickrate.as_f64()
+            .filter(|tickrate| *tickrate >= 1.0 && *tickrate <= 1_000_000.0)
+            .ok_or("invalid tickrate")? as u32),
+        None => None,
+    };
+
+    let mut colors = Vec::new();
+    if let Some(pixels) = rom.get("colors").and_then(|colors| colors.get("pixels")) {
+        for color in pixels.as_array().ok_or("pixel colours are not a list")? {
+            colors.push(color.as_str().and_then(parse_color).ok_or("invalid colour")?);
+        }
+    }
+
+    let mut keys = Vec::new();
+    if let Some(names) = rom.get("keys") {
+        for (name, key) in names.as_object().ok_or("keys are not an object")? {
+            let key = key.as_f64()
+                .filter(|key| key.fract() == 0.0 && *key >= 0.0 && *key < 16.0)
+                .and_then(|key| Key::from_index(key as u8))
+                .ok_or_else(|| format!("invalid key for {}", name))?;
+            keys.push((name.clone(), key));
+        }
+    }
+
+    Ok(RomInfo {
+        title: title.to_string(),
+        platforms,
+        quirks,
+        tickrate,
+        colors,
+        keys,
+    })
+}
+
+/// The quirks of a platform as named in the database, if the emulator
+/// supports it.
+fn platform_quirks(platform: &str) -> Option<Quirks> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(Quirks::cosmac_vip()),
+        // CHIP-8 as most modern interpreters run it, without the
+        // timing related quirks of the VIP.
+        "modernChip8" => Some(Quirks { vf_reset: false, display_wait: false, ..Quirks::cosmac_vip() }),
+        "chip48" => Some(Quirks::chip48()),
+        "superchip1" => Some(Quirks { variant: Variant::SuperChip, ..Quirks::chip48() }),
+        "superchip" => Some(Quirks::superchip()),
+        "xochip" => Some(Quirks::xochip()),
+        _ => None,
+    }
+}
+
+/// Applies the quirk flags of `quirky`, where `true` means the quirky
+/// behaviour that deviates from the original CHIP-8.
+fn apply_quirks(quirks: &mut Quirks, quirky: &Json) -> Result<(), String> {
+    let mut increment_by_x = quirks.load_store == LoadStoreQuirk::IncrementByX;
+    let mut leave_i_unchanged = quirks.load_store == LoadStoreQuirk::Unchanged;
+
+    for (name, value) in quirky.as_object().ok_or("quirks are not an object")? {
+        let value = value.as_bool().ok_or_else(|| format!("quirk {} is not a boolean", name))?;
+        match name.as_str() {
+            "shift" => quirks.shift_uses_vy = !value,
+            "memoryIncrementByX" => increment_by_x = value,
+            "memoryLeaveIUnchanged" => leave_i_unchanged = value,
+            "wrap" => quirks.clip_sprites = !value,
+            "jump" => quirks.jump_uses_vx = value,
+            "vblank" => quirks.display_wait = value,
+            "logic" => quirks.vf_reset = value,
+            // Unknown quirks, which this emulator doesn't emulate.
+            _ => {},
+        }
+    }
+
+    quirks.load_store = if leave_i_unchanged {
+        LoadStoreQuirk::Unchanged
+    } else if increment_by_x {
+        LoadStoreQuirk::IncrementByX
+    } else {
+        LoadStoreQuirk::IncrementByXPlusOne
+    };
+    Ok(())
+}
+
+/// Parses a `#RRGGBB` colour.
+fn parse_color(color: &str) -> Option<(u8, u8, u8)> {
+    if color.len() != 7 || !color.starts_with('#') {
+        return None;
+    }
+    let component = |index: usize| color.get(index..index + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    Some((component(1)?, component(3)?, component(5)?))
+}
+
+#[test]
+fn test_parse() {
+    let database = RomDatabase::parse(r##"[
+        {
+            "title": "Test",
+            "authors": ["Someone"],
+            "roms": {
+                "A9993E364706816ABA3E25717850C26C9CD0D89D": {
+                    "file": "test.ch8",
+                    "platforms": ["megachip8", "superchip", "xochip"],
+                    "quirkyPlatforms": {
+                        "superchip": { "shift": false, "memoryLeaveIUnchanged": false, "wrap": true }
+                    },
+                    "tickrate": 30,
+                    "colors": { "pixels": ["#000000", "#FF8000"], "buzzer": "#FFAA00" },
+                    "keys": { "up": 5, "a": 6 }
+                },
+                "da39a3ee5e6b4b0d3255bfef95601890afd80709": {
+                    "platforms": ["megachip8"]
+                }
+            }
+        }
+    ]"##).unwrap();
+    assert_eq!(database.len(), 2);
+
+    let info = database.lookup(b"abc").unwrap();
+    assert_eq!(info.title, "Test");
+    assert_eq!(info.platforms, ["megachip8", "superchip", "xochip"]);
+    assert_eq!(info.quirks, Some(Quirks {
+        shift_uses_vy: true,
+        load_store: LoadStoreQuirk::IncrementByXPlusOne,
+        clip_sprites: false,
+        ..Quirks::superchip()
+    }));
+    assert_eq!(info.clock_speed(), Some(30 * TIMER_FREQUENCY));
+    assert_eq!(info.colors, [(0x00, 0x00, 0x00), (0xFF, 0x80, 0x00)]);
+    assert_eq!(info.key("up"), Some(Key::Num5));
+    assert_eq!(info.key("b"), None);
+
+    // Unsupported platforms have no quirks, everything else is optional.
+    let info = database.lookup(b"").unwrap();
+    assert_eq!((info.quirks, info.tickrate, info.colors.len(), info.keys.len()), (None, None, 0, 0));
+    assert_eq!(database.lookup(b"abcd"), None);
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = |json: &str| RomDatabase::parse(json).unwrap_err().to_string();
+    assert_eq!(error("{}"), "the database is not an array of programs");
+    assert_eq!(error(r#"[{"roms": {}}]"#), "program 0 has no title");
+    assert_eq!(error(r#"[{"title": "T", "roms": {"00": {}}}]"#), "T, ROM 00: no platforms");
+    assert_eq!(error(r#"[{"title": "T", "roms": {"00": {"platforms": [], "keys": {"up": 16}}}}]"#),
+               "T, ROM 00: invalid key for up");
+    assert_eq!(error(r#"[{"title": "T", "roms": {"00": {"platforms": [], "colors": {"pixels": ["red"]}}}}]"#),
+               "T, ROM 00: invalid colour");
+}
+
+#[test]
+fn test_merge() {
+    let mut database = RomDatabase::parse(r#"[{"title": "Old", "roms": {
+        "a9993e364706816aba3e25717850c26c9cd0d89d": {"platforms": ["originalChip8"]}
+    }}]"#).unwrap();
+    database.merge(RomDatabase::parse(r#"[{"title": "New", "roms": {
+        "a9993e364706816aba3e25717850c26c9cd0d89d": {"platforms": ["modernChip8"], "tickrate": 15}
+    }}]"#).unwrap());
+
+    let info = database.lookup(b"abc").unwrap();
+    assert_eq!((info.title.as_str(), info.tickrate), ("New", Some(15)));
+    assert_eq!(info.quirks.map(|quirks| quirks.display_wait), Some(false));
+}
+
+#[test]
+fn test_embedded() {
+    use MAX_CLOCK_SPEED;
+    // Panics if the file doesn't parse.
+    let database = RomDatabase::embedded();
+    for (hash, info) in database.roms.iter() {
+        assert!(hash.len() == 40 && hash.bytes().all(|digit| digit.is_ascii_hexdigit()), "{}: bad hash", hash);
+        assert_eq!(database.get(&hash.to_uppercase()), Some(info));
+        if info.platforms.iter().any(|platform| platform_quirks(platform).is_some()) {
+            assert!(info.quirks.is_some(), "{}: no quirks", info.title);
+        }
+        if let Some(clock_speed) = info.clock_speed() {
+            assert!(clock_speed <= MAX_CLOCK_SPEED, "{}: too fast", info.title);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//! Save states, which capture the complete machine in a versioned
+//! binary format.
+//!
+//! All multi-byte values are little-endian. Version 2 is laid out as
+//!
+//! ```text
+//! magic          4 bytes  "C8ST"
+//! version        u16
+//! variant        u8       0 = CHIP-8, 1 = SUPER-CHIP, 2 = XO-CHIP
+//! shift_uses_vy  u8       booleans are 0 or 1
+//! load_store     u8       0 = X + 1, 1 = X, 2 = unchanged
+//! jump_uses_vx   u8
+//! vf_reset       u8
+//! clip_sprites   u8
+//! display_wait   u8
+//! pc             u16
+//! I              u16
+//! V0 to VF       16 bytes
+//! sp             u8       0 to 16
+//! stack          16 x u16
+//! delay timer    u8
+//! sound timer    u8
+//! keypad         u16      bit N is set while key N is held
+//! key presses    u16      keys pressed since FX0A started waiting
+//! key wait       u8       0 = idle, 1 = waiting for a press,
+//!                         2 = waiting for the release of the next byte
+//! key            u8
+//! vblank wait    u8
+//! exited         u8
+//! hires          u8
+//! planes         u8       0 to 3
+//! flags          16 bytes
+//! audio pattern  16 bytes
+//! pitch          u8
+//! clock speed    u32      1 to MAX_CLOCK_SPEED
+//! cycle budget   u64
+//! timer budget   u64
+//! seed           u32      the random number generator was seeded with
+//! rng state      u64      as given by `Rng::state`
+//! memory         4KB, or 64KB on XO-CHIP
+//! screen         64x32 bytes, or 128x64 in hi-res mode
+//! checksum       u32      CRC-32 of everything before it
+//! ```
+//!
+//! Version 1 lacks the seed and the random number generator state.
+//! The audio sink, the trace, the rewind history and the load address
+//! are not part of the save state.
+
+use std::mem;
+use error::StateError;
+use keypad::Key;
+use quirks::{LoadStoreQuirk, Quirks, Variant};
+use display::Display;
+use {Chip8, KeyWait, MAX_CLOCK_SPEED, NANOS_PER_SECOND};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// The format version written by `save_state`.
+pub const STATE_VERSION: u16 = 2;
+
+/// CRC-32 as used by zlib and PNG.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub(crate) struct Writer(Vec<u8>);
+
+impl Writer {
+    /// Starts a file with `magic` and `version`.
+    pub(crate) fn new(magic: &[u8; 4], version: u16, capacity: usize) -> Writer {
+        let mut out = Writer(Vec::with_capacity(capacity));
+        out.bytes(magic);
+        out.u16(version);
+        out
+    }
+
+    /// Appends the checksum and returns the finished file.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let checksum = crc32(&self.0);
+        self.u32(checksum);
+        self.0
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.0.push(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Checks that `data` starts with `magic` and a version between 1
+    /// and `latest`, and returns the version along with a reader for
+    /// the rest.
+    pub(crate) fn new(data: &'a [u8], magic: &[u8; 4], latest: u16) -> Result<(Reader<'a>, u16), StateError> {
+        if !data.starts_with(&magic[..data.len().min(magic.len())]) {
+            return Err(StateError::InvalidMagic);
+        }
+        if data.len() < magic.len() {
+            return Err(StateError::Truncated);
+        }
+        let mut input = Reader { data, position: magic.len() };
+
+        let version = input.u16()?;
+        if version == 0 || version > latest {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        Ok((input, version))
+    }
+
+    /// Verifies the checksum, which has to end the data.
+    pub(crate) fn finish(mut self) -> Result<(), StateError> {
+        let end = self.position;
+        if self.u32()? != crc32(&self.data[..end]) || self.position != self.data.len() {
+            return Err(StateError::Corrupt);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() - self.position < len {
+            return Err(StateError::Truncated);
+        }
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn array(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+        out.copy_from_slice(self.bytes(out.len())?);
+        Ok(())
+    }
+}
+
+pub(crate) fn write_quirks(out: &mut Writer, quirks: &Quirks) {
+    out.u8(match quirks.variant {
+        Variant::Chip8 => 0,
+        Variant::SuperChip => 1,
+        Variant::XoChip => 2,
+    });
+    out.bool(quirks.shift_uses_vy);
+    out.u8(match quirks.load_store {
+        LoadStoreQuirk::IncrementByXPlusOne => 0,
+        LoadStoreQuirk::IncrementByX => 1,
+        LoadStoreQuirk::Unchanged => 2,
+    });
+    out.bool(quirks.jump_uses_vx);
+    out.bool(quirks.vf_reset);
+    out.bool(quirks.clip_sprites);
+    out.bool(quirks.display_wait);
+}
+
+pub(crate) fn read_quirks(input: &mut Reader) -> Result<Quirks, StateError> {
+    let variant = match input.u8()? {
+        0 => Variant::Chip8,
+        1 => Variant::SuperChip,
+        2 => Variant::XoChip,
+        _ => return Err(StateError::Corrupt),
+    };
+    let shift_uses_vy = input.bool()?;
+    let load_store = match input.u8()? {
+        0 => LoadStoreQuirk::IncrementByXPlusOne,
+        1 => LoadStoreQuirk::IncrementByX,
+        2 => LoadStoreQuirk::Unchanged,
+        _ => return Err(StateError::Corrupt),
+    };
+    Ok(Quirks {
+        variant,
+        shift_uses_vy,
+        load_store,
+        jump_uses_vx: input.bool()?,
+        vf_reset: input.bool()?,
+        clip_sprites: input.bool()?,
+        display_wait: input.bool()?,
+    })
+}
+
+impl Chip8 {
+    /// Captures the complete state of the machine, including memory,
+    /// the screen and the quirks it runs with. See the `state` module
+    /// for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Writer::new(MAGIC, STATE_VERSION, self.memory.len() + self.display.pixels().len() + 128);
+        write_quirks(&mut out, &self.quirks);
+
+        out.u16(self.pc);
+        out.u16(self.I);
+        out.bytes(&self.V);
+        out.u8(self.sp as u8);
+        for address in self.stack.iter() {
+            out.u16(*address);
+        }
+        out.u8(self.delay_timer);
+        out.u8(self.sound_timer);
+
+        out.u16(self.keypad());
+        out.u16(self.key_presses);
+        let (wait, key) = match self.key_wait {
+            KeyWait::Idle => (0, 0),
+            KeyWait::Press => (1, 0),
+            KeyWait::Release(key) => (2, key),
+        };
+        out.u8(wait);
+        out.u8(key);
+        out.bool(self.awaiting_vblank);
+        out.bool(self.exited);
+
+        out.bool(self.display.is_hires());
+        out.u8(self.planes);
+        out.bytes(&self.flags);
+        out.bytes(&self.audio_pattern);
+        out.u8(self.pitch);
+
+        out.u32(self.clock_speed);
+        out.u64(self.cycle_budget);
+        out.u64(self.timer_budget);
+
+        out.u32(self.seed);
+        out.u64(self.rng.state());
+
+        out.bytes(&self.memory);
+        out.bytes(self.display.pixels());
+        out.finish()
+    }
+
+    /// Restores a state captured by `save_state`. On error the machine
+    /// is left unchanged.
+    ///
+    /// The random number generator, the audio sink, the trace, the
+    /// rewind history and the load address are kept. The generator is
+    /// moved to the saved state, except with version 1 states, which
+    /// leave it as it is, and the sink is told if the buzzer starts or
+    /// stops.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let (mut input, version) = Reader::new(data, MAGIC, STATE_VERSION)?;
+        let mut game = Chip8::with_quirks(read_quirks(&mut input)?);
+
+        game.pc = input.u16()?;
+        game.I = input.u16()?;
+        input.array(&mut game.V)?;
+        game.sp = input.u8()? as u16;
+        if game.sp as usize > game.stack.len() {
+            return Err(StateError::Corrupt);
+        }
+        for address in game.stack.iter_mut() {
+            *address = input.u16()?;
+        }
+        game.delay_timer = input.u8()?;
+        game.sound_timer = input.u8()?;
+
+        let keypad = input.u16()?;
+        for key in Key::all() {
+            game.key[key.index() as usize] = (keypad >> key.index()) as u8 & 1;
+        }
+        game.key_presses = input.u16()?;
+        game.key_wait = match (input.u8()?, input.u8()?) {
+            (0, 0) => KeyWait::Idle,
+            (1, 0) => KeyWait::Press,
+            (2, key) if key < 16 => KeyWait::Release(key),
+            _ => return Err(StateError::Corrupt),
+        };
+        game.awaiting_vblank = input.bool()?;
+        game.exited = input.bool()?;
+
+        let hires = input.bool()?;
+        game.planes = input.u8()?;
+        if game.planes > 3 {
+            return Err(StateError::Corrupt);
+        }
+        input.array(&mut game.flags)?;
+        input.array(&mut game.audio_pattern)?;
+        game.pitch = input.u8()?;
+
+        game.clock_speed = input.u32()?;
+        if game.clock_speed == 0 || game.clock_speed > MAX_CLOCK_SPEED {
+            return Err(StateError::Corrupt);
+        }
+        // Budgets are fractions of a second.
+        game.cycle_budget = input.u64()?;
+        game.timer_budget = input.u64()?;
+        if game.cycle_budget >= NANOS_PER_SECOND || game.timer_budget >= NANOS_PER_SECOND {
+            return Err(StateError::Corrupt);
+        }
+
+        let rng = if version >= 2 { Some((input.u32()?, input.u64()?)) } else { None };
+
+        input.array(&mut game.memory)?;
+        game.display = Display::new(hires);
+        input.array(game.display.pixels_mut())?;
+        if game.display.pixels().iter().any(|pixel| *pixel > 3) {
+            return Err(StateError::Corrupt);
+        }
+
+        input.finish()?;
+
+        let was_beeping = self.is_beeping();
+        mem::swap(&mut game.rng, &mut self.rng);
+        match rng {
+            Some((seed, state)) => {
+                game.seed = seed;
+                game.rng.set_state(state);
+            },
+            None => game.seed = self.seed,
+        }
+        game.audio_sink = self.audio_sink.take();
+        game.history = self.history.take();
+        game.trace = self.trace.take();
+        game.load_address = self.load_address;
+        *self = game;
+
+        if let Some(ref mut sink) = self.audio_sink {
+            if self.sound_timer > 0 && !was_beeping {
+                sink.start_tone();
+            } else if self.sound_timer == 0 && was_beeping {
+                sink.stop_tone();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn running_game() -> Chip8 {
+    let mut game = Chip8::with_quirks(Quirks::xochip());
+    // Draws the font sprite for 5 in both planes, then loops.
+    let program = [0xF3, 0x01, 0x60, 0x05, 0xF0, 0x29, 0xD1, 0x25, 0x63, 0x20, 0xF3, 0x18, 0x12, 0x0C];
+    game.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    game.run_frame(6).unwrap();
+    game.key_down(Key::A);
+    game
+}
+
+#[test]
+fn test_save_and_load_state() {
+    let game = running_game();
+    let state = game.save_state();
+
+    let mut loaded = Chip8::new();
+    loaded.load_state(&state).unwrap();
+
+    assert_eq!(loaded.quirks(), Quirks::xochip());
+    assert_eq!(loaded.pc(), game.pc());
+    assert_eq!(loaded.v(), game.v());
+    assert!(loaded.is_beeping());
+    assert_eq!(loaded.sound_timer(), game.sound_timer());
+    assert_eq!(loaded.keypad(), 1 << 0xA);
+    assert_eq!(loaded.memory(), game.memory());
+    assert_eq!(loaded.display(), game.display());
+    assert_eq!(loaded.save_state(), state);
+}
+
+#[test]
+fn test_save_state_random_numbers() {
+    let mut game = Chip8::new();
+    game.set_seed(99);
+    // Fills V0 with random numbers in a loop.
+    game.memory[0x200..0x204].copy_from_slice(&[0xC0, 0xFF, 0x12, 0x00]);
+    game.run_frame(10).unwrap();
+    let state = game.save_state();
+
+    let mut loaded = Chip8::new();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.seed(), 99);
+    for _ in 0..10 {
+        game.cycle().unwrap();
+        loaded.cycle().unwrap();
+        assert_eq!(loaded.v()[0], game.v()[0]);
+    }
+
+    // Version 1 has neither the seed nor the generator state, which
+    // are the 12 bytes after the timer budget.
+    let mut v1 = [&state[..131], &state[143..state.len() - 4]].concat();
+    v1[4] = 1;
+    let checksum = crc32(&v1);
+    v1.extend_from_slice(&checksum.to_le_bytes());
+    let mut loaded = Chip8::new();
+    loaded.set_seed(5);
+    loaded.load_state(&v1).unwrap();
+    assert_eq!(loaded.seed(), 5);
+    assert_eq!(loaded.memory(), game.memory());
+}
+
+#[test]
+fn test_load_state_errors() {
+    let state = running_game().save_state();
+    let mut game = Chip8::new();
+    let before = game.save_state();
+
+    assert_eq!(game.load_state(b"NES\x1a"), Err(StateError::InvalidMagic));
+    assert_eq!(game.load_state(b"C8"), Err(StateError::Truncated));
+
+    let mut newer = state.clone();
+    newer[4] = 3;
+    assert_eq!(game.load_state(&newer), Err(StateError::UnsupportedVersion(3)));
+
+    for len in [6, 40, 100, state.len() - 1].iter() {
+        assert_eq!(game.load_state(&state[..*len]), Err(StateError::Truncated));
+    }
+
+    let mut flipped = state.clone();
+    flipped[0x300] ^= 1;
+    assert_eq!(game.load_state(&flipped), Err(StateError::Corrupt));
+
+    let mut invalid_variant = state.clone();
+    invalid_variant[6] = 7;
+    assert_eq!(game.load_state(&invalid_variant), Err(StateError::Corrupt));
+
+    let mut trailing = state.clone();
+    trailing.push(0);
+    assert_eq!(game.load_state(&trailing), Err(StateError::Corrupt));
+
+    // Out of range values with a valid checksum: the clock speed, then
+    // the cycle and timer budgets, which start at offset 111.
+    for &(offset, value) in [(111, &[0xFF; 4][..]), (115, &[0xFF; 8][..]), (123, &1_000_000_000u64.to_le_bytes()[..])].iter() {
+        let mut invalid = state[..state.len() - 4].to_vec();
+        invalid[offset..offset + value.len()].copy_from_slice(value);
+        let checksum = crc32(&invalid);
+        invalid.extend_from_slice(&checksum.to_le_bytes());
+        assert_eq!(game.load_state(&invalid), Err(StateError::Corrupt));
+    }
+
+    assert_eq!(game.save_state(), before);
+}
@@ -0,0 +1,333 @@
+//! Reading the keypad from and drawing the screen to a text terminal,
+//! as done by `chip8 term`.
+//!
+//! Terminals send the bytes of the keys typed, or escape sequences for
+//! keys like the arrows, but never say when a key is released. `Keys`
+//! therefore holds every key down for a while after it was typed,
+//! long enough for the terminal to start repeating a key that is held
+//! down, and a little after each repeat.
+
+use std::mem;
+use display::Display;
+use keypad::Key;
+
+/// Maps the conventional QWERTY layout to the hex keypad, like the SDL
+/// player.
+pub const KEY_MAP: [(u8, Key); 16] = [
+    (b'1', Key::Num1), (b'2', Key::Num2), (b'3', Key::Num3), (b'4', Key::C),
+    (b'q', Key::Num4), (b'w', Key::Num5), (b'e', Key::Num6), (b'r', Key::D),
+    (b'a', Key::Num7), (b's', Key::Num8), (b'd', Key::Num9), (b'f', Key::E),
+    (b'z', Key::A), (b'x', Key::Num0), (b'c', Key::B), (b'v', Key::F),
+];
+
+/// Keys for the inputs the ROM database names for a ROM. The arrow keys
+/// arrive as `ESC [ A` to `ESC [ D`.
+pub const INPUT_MAP: [(&str, &[u8]); 6] = [
+    ("up", b"\x1b[A"), ("down", b"\x1b[B"), ("right", b"\x1b[C"), ("left", b"\x1b[D"),
+    ("a", b" "), ("b", b"\r"),
+];
+
+/// Frames a key stays down after each repeat of the terminal, which
+/// comfortably covers the usual 25 to 30 repeats per second.
+pub const REPEAT_HOLD: u32 = 6;
+
+/// Frames without input after which an Escape that could start an
+/// escape sequence counts as the Escape key.
+pub const ESCAPE_TIMEOUT: u32 = 3;
+
+const CTRL_C: u8 = 0x03;
+const ESCAPE: u8 = 0x1B;
+
+/// Emulates key releases by holding keys down for a number of frames
+/// after every press.
+pub struct Keys {
+    /// Frames left until each key is released.
+    held: [u32; 16],
+    hold: u32,
+}
+
+impl Keys {
+    /// Holds keys down for `hold` frames after they were first typed,
+    /// which has to be longer than the delay before the terminal
+    /// starts repeating a key to keep it down without interruption.
+    pub fn new(hold: u32) -> Keys {
+        Keys { held: [0; 16], hold }
+    }
+
+    /// Presses `key`. If it is still down, the terminal is repeating
+    /// it, so it is only held for `REPEAT_HOLD` more frames.
+    pub fn press(&mut self, key: Key) {
+        let frames = &mut self.held[key.index() as usize];
+        *frames = if *frames > 0 { (*frames).max(REPEAT_HOLD) } else { self.hold };
+    }
+
+    /// The keys held down, as a bitmask for `Chip8::set_keypad`.
+    pub fn keypad(&self) -> u16 {
+        self.held.iter().enumerate()
+            .filter(|&(_, frames)| *frames > 0)
+            .fold(0, |keys, (key, _)| keys | 1 << key)
+    }
+
+    /// Counts down the frames of all keys.
+    pub fn next_frame(&mut self) {
+        for frames in self.held.iter_mut() {
+            *frames = frames.saturating_sub(1);
+        }
+    }
+}
+
+/// Turns the bytes the terminal sends into key presses.
+pub struct Input {
+    /// Byte sequences for keys, used before `KEY_MAP`.
+    inputs: Vec<(&'static [u8], Key)>,
+    /// The start of an escape sequence whose rest hasn't arrived yet.
+    pending: Vec<u8>,
+    /// Frames the pending bytes have been waiting for the rest.
+    waited: u32,
+}
+
+impl Input {
+    /// Maps the byte sequences in `inputs` to their keys, and all other
+    /// bytes through `KEY_MAP`.
+    pub fn new(inputs: Vec<(&'static [u8], Key)>) -> Input {
+        Input { inputs, pending: Vec::new(), waited: 0 }
+    }
+
+    /// Presses the keys for the bytes the terminal sent during a
+    /// frame, which may be none. Returns false if the user asked to
+    /// quit, with Ctrl-C or Escape.
+    ///
+    /// A read can end in the middle of an escape sequence, so an
+    /// incomplete one is kept for the next frame. An Escape on its own
+    /// only quits after `ESCAPE_TIMEOUT` frames without anything
+    /// following it.
+    pub fn handle(&mut self, bytes: &[u8], keys: &mut Keys) -> bool {
+        if bytes.is_empty() {
+            if !self.pending.is_empty() {
+                self.waited += 1;
+                if self.waited >= ESCAPE_TIMEOUT {
+                    let escape = self.pending == [ESCAPE];
+                    self.pending.clear();
+                    return !escape;
+                }
+            }
+            return true;
+        }
+
+        self.waited = 0;
+        let mut buffer = mem::take(&mut self.pending);
+        buffer.extend_from_slice(bytes);
+        let mut rest = &buffer[..];
+        while let Some(&byte) = rest.first() {
+            if let Some(&(sequence, key)) = self.inputs.iter().find(|input| rest.starts_with(input.0)) {
+                keys.press(key);
+                rest = &rest[sequence.len()..];
+                continue;
+            }
+            match byte {
+                CTRL_C => return false,
+                // Skips escape sequences of other keys.
+                ESCAPE => match escape_len(rest) {
+                    Some(len) => rest = &rest[len..],
+                    None => {
+                        self.pending = rest.to_vec();
+                        break;
+                    },
+                },
+                _ => {
+                    let byte = byte.to_ascii_lowercase();
+                    if let Some(&(_, key)) = KEY_MAP.iter().find(|&&(character, _)| character == byte) {
+                        keys.press(key);
+                    }
+                    rest = &rest[1..];
+                },
+            }
+        }
+        true
+    }
+}
+
+/// The length of the escape sequence at the start of `bytes`, or None
+/// if it isn't complete yet.
+fn escape_len(bytes: &[u8]) -> Option<usize> {
+    match bytes.get(1) {
+        None => None,
+        Some(&b'[') => bytes.iter().skip(2).position(|byte| byte.is_ascii_alphabetic() || *byte == b'~').map(|end| end + 3),
+        // Alt and a key, of which only the key counts.
+        Some(_) => Some(1),
+    }
+}
+
+/// Draws the screen with the upper half block, in the colour of the
+/// top pixel on the colour of the bottom one. `palette` holds the
+/// colours for the four combinations of XO-CHIP planes.
+pub fn half_blocks(display: &Display, palette: &[(u8, u8, u8); 4], out: &mut String) {
+    for y in (0..display.height()).step_by(2) {
+        let (top, bottom) = (display.row(y), display.row(y + 1));
+        let mut last = None;
+        for (top, bottom) in top.iter().zip(bottom.iter()) {
+            let colors = (palette[*top as usize & 0b11], palette[*bottom as usize & 0b11]);
+            if last != Some(colors) {
+                let ((r, g, b), (br, bg, bb)) = colors;
+                out.push_str(&format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", r, g, b, br, bg, bb));
+                last = Some(colors);
+            }
+            out.push('\u{2580}');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+}
+
+/// Draws the screen with braille characters, with a dot for every
+/// pixel that is set in any plane.
+pub fn braille(display: &Display, out: &mut String) {
+    // The bit of each dot, by its row and column in the character.
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    for y in (0..display.height()).step_by(4) {
+        for x in (0..display.width()).step_by(2) {
+            let mut dots = 0;
+            for (dy, row) in DOTS.iter().enumerate() {
+                for (dx, dot) in row.iter().enumerate() {
+                    if display.pixel(x + dx, y + dy) != 0 {
+                        dots |= dot;
+                    }
+                }
+            }
+            out.push(std::char::from_u32(0x2800 + dots).unwrap());
+        }
+        out.push_str("\r\n");
+    }
+}
+
+/// The font sprite for 0, F0 90 90 90 F0, in the top left corner.
+#[cfg(test)]
+fn zero() -> Display {
+    let mut display = Display::new(false);
+    for (y, row) in [0xF0u8, 0x90, 0x90, 0x90, 0xF0].iter().enumerate() {
+        for x in 0..8 {
+            if row & 0x80 >> x != 0 {
+                display.flip(x, y, 1);
+            }
+        }
+    }
+    display
+}
+
+#[test]
+fn test_input() {
+    let mut keys = Keys::new(2);
+    let mut input = Input::new(Vec::new());
+    assert!(input.handle(b"1W", &mut keys));
+    assert_eq!(keys.keypad(), 1 << 0x1 | 1 << 0x5);
+
+    // Arrow keys only count if the database maps them, and the letter
+    // ending their escape sequence is no key of its own.
+    let mut keys = Keys::new(2);
+    assert!(input.handle(b"\x1b[A\x1b[15~x\x1bq", &mut keys));
+    assert_eq!(keys.keypad(), 1 << 0x0 | 1 << 0x4);
+    let mut keys = Keys::new(2);
+    let mut input = Input::new(vec![(b"\x1b[B", Key::Num8)]);
+    assert!(input.handle(b"\x1b[A\x1b[B", &mut keys));
+    assert_eq!(keys.keypad(), 1 << 0x8);
+
+    assert!(!input.handle(b"w\x03", &mut keys));
+}
+
+#[test]
+fn test_input_escape() {
+    // An escape sequence split between two reads.
+    let mut keys = Keys::new(2);
+    let mut input = Input::new(vec![(b"\x1b[B", Key::Num8)]);
+    assert!(input.handle(b"x\x1b", &mut keys));
+    assert!(input.handle(b"", &mut keys));
+    assert!(input.handle(b"[", &mut keys));
+    assert!(input.handle(b"B", &mut keys));
+    assert_eq!(keys.keypad(), 1 << 0x0 | 1 << 0x8);
+
+    // Escape on its own quits once nothing followed it for a while.
+    assert!(input.handle(b"\x1b", &mut keys));
+    for _ in 1..ESCAPE_TIMEOUT {
+        assert!(input.handle(b"", &mut keys));
+    }
+    assert!(!input.handle(b"", &mut keys));
+
+    // An incomplete sequence is dropped.
+    let mut input = Input::new(Vec::new());
+    assert!(input.handle(b"\x1b[1", &mut keys));
+    for _ in 0..ESCAPE_TIMEOUT {
+        assert!(input.handle(b"", &mut keys));
+    }
+    assert!(input.handle(b"", &mut keys));
+}
+
+#[test]
+fn test_keys_hold() {
+    let mut keys = Keys::new(2);
+    keys.press(Key::A);
+    keys.next_frame();
+    keys.press(Key::B);
+    assert_eq!(keys.keypad(), 1 << 0xA | 1 << 0xB);
+    keys.next_frame();
+    assert_eq!(keys.keypad(), 1 << 0xB);
+    keys.next_frame();
+    assert_eq!(keys.keypad(), 0);
+}
+
+#[test]
+fn test_keys_repeat() {
+    let mut keys = Keys::new(30);
+    keys.press(Key::A);
+    for _ in 0..29 {
+        keys.next_frame();
+    }
+    // The first repeat arrives before the key is released, and each
+    // repeat holds it for a few more frames.
+    keys.press(Key::A);
+    for _ in 0..REPEAT_HOLD - 1 {
+        keys.next_frame();
+        assert_eq!(keys.keypad(), 1 << 0xA);
+    }
+    keys.press(Key::A);
+    for _ in 0..REPEAT_HOLD - 1 {
+        keys.next_frame();
+    }
+    assert_eq!(keys.keypad(), 1 << 0xA);
+    keys.next_frame();
+    assert_eq!(keys.keypad(), 0);
+
+    // Repeats don't cut the first hold short.
+    keys.press(Key::A);
+    keys.press(Key::A);
+    for _ in 0..29 {
+        keys.next_frame();
+    }
+    assert_eq!(keys.keypad(), 1 << 0xA);
+}
+
+#[test]
+fn test_half_blocks() {
+    let palette = [(0, 0, 0), (1, 1, 1), (2, 2, 2), (3, 3, 3)];
+    let mut out = String::new();
+    half_blocks(&zero(), &palette, &mut out);
+
+    let lines: Vec<&str> = out.split_terminator("\r\n").collect();
+    assert_eq!(lines.len(), 16);
+    // The top pixels are F0, the bottom ones 90.
+    assert_eq!(lines[0], format!(
+        "\x1b[38;2;1;1;1m\x1b[48;2;1;1;1m\u{2580}\x1b[38;2;1;1;1m\x1b[48;2;0;0;0m\u{2580}\u{2580}\
+         \x1b[38;2;1;1;1m\x1b[48;2;1;1;1m\u{2580}\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m{}\x1b[0m",
+        "\u{2580}".repeat(60),
+    ));
+}
+
+#[test]
+fn test_braille() {
+    let mut out = String::new();
+    braille(&zero(), &mut out);
+
+    let lines: Vec<&str> = out.split_terminator("\r\n").collect();
+    assert_eq!(lines.len(), 8);
+    assert_eq!(lines[0], format!("\u{284F}\u{28B9}{}", "\u{2800}".repeat(30)));
+    assert_eq!(lines[1], format!("\u{2809}\u{2809}{}", "\u{2800}".repeat(30)));
+    assert_eq!(lines[2], "\u{2800}".repeat(32));
+}
@@ -0,0 +1,175 @@
+//! Execution tracing, which logs every executed instruction together
+//! with the machine state before it, one line each:
+//!
+//! ```text
+//! 0200 6005 LD V0, #05           V 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 SP 00 DT 00 ST 00
+//! ```
+//!
+//! All fields have a fixed width, so traces of two runs can be
+//! compared line by line. An instruction that fails with a
+//! `Chip8Error` is not traced, like it has no other effect.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use disasm::{self, Syntax};
+use Chip8;
+
+/// Where and which instructions are traced.
+pub struct Trace {
+    out: Box<dyn Write>,
+    addresses: RangeInclusive<u16>,
+    limit: Option<u64>,
+    lines: u64,
+    /// The first error writing to `out`, after which nothing more is
+    /// written.
+    error: Option<io::Error>,
+}
+
+impl Trace {
+    /// Traces all instructions to `out`.
+    pub fn new(out: Box<dyn Write>) -> Trace {
+        Trace {
+            out,
+            addresses: 0..=0xFFFF,
+            limit: None,
+            lines: 0,
+            error: None,
+        }
+    }
+
+    /// Only traces instructions at `addresses`.
+    pub fn addresses(mut self, addresses: RangeInclusive<u16>) -> Trace {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Stops tracing after `lines` lines.
+    pub fn limit(mut self, lines: u64) -> Trace {
+        self.limit = Some(lines);
+        self
+    }
+
+    /// Number of lines written so far.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// Flushes the output and reports the first error that occurred
+    /// while tracing.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush(),
+        }
+    }
+
+    /// Whether the instruction at `pc` is to be traced.
+    fn wants(&self, pc: u16) -> bool {
+        let full = self.limit.is_some_and(|limit| self.lines >= limit);
+        self.error.is_none() && !full && self.addresses.contains(&pc)
+    }
+
+    fn write_line(&mut self, line: &str) {
+        match writeln!(self.out, "{}", line) {
+            Ok(()) => self.lines += 1,
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+/// The trace line for the instruction at the program counter of
+/// `game`.
+fn format_line(game: &Chip8) -> String {
+    let pc = game.pc();
+    let memory = game.memory();
+    let opcode = (memory[pc as usize] as u16) << 8 | memory[(pc as usize + 1) % memory.len()] as u16;
+    let instruction = disasm::instruction_at(memory, pc, game.quirks().variant, Syntax::Classic).unwrap_or_default();
+    let registers: Vec<String> = game.v().iter().map(|value| format!("{:02X}", value)).collect();
+
+    format!("{:04X} {:04X} {:20} V {} I {:04X} SP {:02X} DT {:02X} ST {:02X}",
+            pc, opcode, instruction, registers.join(" "), game.i(), game.stack().len(),
+            game.delay_timer(), game.sound_timer())
+}
+
+impl Chip8 {
+    /// Logs every instruction executed from now on as described by
+    /// `trace`, replacing any previous trace.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    /// Stops tracing and returns the trace, to `finish` it.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    /// The trace line for the instruction at the program counter, if
+    /// the trace is enabled and interested in it. The line describes
+    /// the machine before the instruction and is only written with
+    /// `write_trace` once the instruction succeeded.
+    pub(crate) fn trace_line(&self) -> Option<String> {
+        match self.trace {
+            Some(ref trace) if trace.wants(self.pc()) => Some(format_line(self)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn write_trace(&mut self, line: &str) {
+        if let Some(ref mut trace) = self.trace {
+            trace.write_line(line);
+        }
+    }
+}
+
+#[cfg(test)]
+struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_trace() {
+    let buffer = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut game = Chip8::new();
+    // Counts up V0 in a loop.
+    let program = [0x60, 0x05, 0x70, 0x01, 0x12, 0x02];
+    game.memory[0x200..0x206].copy_from_slice(&program);
+    game.set_trace(Trace::new(Box::new(SharedBuffer(buffer.clone()))).addresses(0x202..=0x2FF).limit(3));
+
+    for _ in 0..10 {
+        game.cycle().unwrap();
+    }
+    let trace = game.take_trace().unwrap();
+    assert_eq!(trace.lines(), 3);
+    trace.finish().unwrap();
+
+    let output = String::from_utf8(buffer.borrow().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines, [
+        "0202 7001 ADD V0, #01          V 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 SP 00 DT 00 ST 00",
+        "0204 1202 JP #202              V 06 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 SP 00 DT 00 ST 00",
+        "0202 7001 ADD V0, #01          V 06 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 SP 00 DT 00 ST 00",
+    ]);
+}
+
+#[test]
+fn test_trace_fault() {
+    let buffer = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut game = Chip8::new();
+    // 00EE with an empty stack.
+    game.memory[0x200..0x202].copy_from_slice(&[0x00, 0xEE]);
+    game.set_trace(Trace::new(Box::new(SharedBuffer(buffer.clone()))));
+
+    assert!(game.cycle().is_err());
+    let trace = game.take_trace().unwrap();
+    assert_eq!(trace.lines(), 0);
+    assert!(buffer.borrow().is_empty());
+}
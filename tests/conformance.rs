@@ -0,0 +1,251 @@
+//! Runs the test ROMs in `tests/roms` with each quirks preset for a
+//! fixed number of frames and compares the screen to the golden images
+//! in `tests/golden`, named `ROM-PRESET.pbm`.
+//!
+//! The ROMs were written for these tests and are assembled from the
+//! `.8o` sources next to them with `chip8 asm`. They check the
+//! emulator against the behaviour documented for each platform, not
+//! against an independent implementation like the public test suites
+//! would.
+//!
+//! The golden images only catch changes in behaviour, so the screens of
+//! the ROMs whose result is documented in their source are also checked
+//! against a screen drawn here from that documentation.
+//!
+//! After a deliberate change in behaviour, run the tests with
+//! `CHIP8_BLESS=1` to write new golden images, and look at the
+//! differences before committing them.
+
+extern crate chip8;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use chip8::{asm, Chip8, Key, Quirks};
+
+/// Instructions per frame. Enough for a frame to fit the twenty
+/// sprites of the vblank test in `platform.8o`.
+const TICKRATE: u32 = 100;
+
+const ALL_PRESETS: &[&str] = &["vip", "chip48", "schip", "xochip"];
+const SUPERCHIP_PRESETS: &[&str] = &["schip", "xochip"];
+
+struct TestRom {
+    name: &'static str,
+    frames: u32,
+    /// `(frame, key, down)` events applied at the start of the frame.
+    keys: &'static [(u32, u8, bool)],
+    presets: &'static [&'static str],
+}
+
+const ROMS: [TestRom; 6] = [
+    TestRom { name: "banner", frames: 60, keys: &[], presets: ALL_PRESETS },
+    TestRom { name: "instructions", frames: 120, keys: &[], presets: ALL_PRESETS },
+    TestRom { name: "vf", frames: 120, keys: &[], presets: ALL_PRESETS },
+    TestRom { name: "platform", frames: 120, keys: &[], presets: ALL_PRESETS },
+    TestRom {
+        name: "input",
+        frames: 120,
+        keys: &[(5, 0x7, true), (10, 0x7, false), (20, 0x1, true), (20, 0xA, true)],
+        presets: ALL_PRESETS,
+    },
+    TestRom { name: "hires", frames: 60, keys: &[], presets: SUPERCHIP_PRESETS },
+];
+
+fn path(directory: &str, file: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", directory, file].iter().collect()
+}
+
+fn test_rom(name: &str) -> &'static TestRom {
+    ROMS.iter().find(|rom| rom.name == name).unwrap()
+}
+
+/// Runs `rom` with the quirks `preset` and returns the machine.
+fn run(rom: &TestRom, preset: &str) -> Chip8 {
+    let data = fs::read(path("roms", &format!("{}.ch8", rom.name))).unwrap();
+    let mut game = Chip8::with_quirks(Quirks::preset(preset).unwrap());
+    game.load_rom(&data).unwrap();
+
+    for frame in 0..rom.frames {
+        for &(_, key, down) in rom.keys.iter().filter(|event| event.0 == frame) {
+            let key = Key::from_index(key).unwrap();
+            if down {
+                game.key_down(key);
+            } else {
+                game.key_up(key);
+            }
+        }
+        if let Err(err) = game.run_frame(TICKRATE) {
+            panic!("{} with {}: {}", rom.name, preset, err);
+        }
+    }
+    game
+}
+
+/// The screen of `game` as a PBM image.
+fn pbm(game: &Chip8) -> String {
+    let mut image = Vec::new();
+    game.write_pbm(&mut image).unwrap();
+    String::from_utf8(image).unwrap()
+}
+
+/// Compares the screens of `name` with all its presets to the golden
+/// images, or replaces the golden images with `CHIP8_BLESS` set.
+fn check(name: &str) {
+    let rom = test_rom(name);
+    let bless = env::var_os("CHIP8_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for preset in rom.presets {
+        let file = format!("{}-{}.pbm", name, preset);
+        let golden = path("golden", &file);
+        let image = pbm(&run(rom, preset));
+        if bless {
+            fs::write(&golden, &image).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|err| {
+            panic!("could not read {}: {}, run with CHIP8_BLESS=1 to create it", golden.display(), err)
+        });
+        if image != expected {
+            let actual = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(&file);
+            fs::write(&actual, &image).unwrap();
+            failures.push(format!("{} differs from {}", actual.display(), golden.display()));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// A low resolution screen, drawn from the expectations in the source
+/// of a ROM rather than by the emulator.
+struct Screen {
+    pixels: [[bool; 64]; 32],
+}
+
+impl Screen {
+    fn new() -> Screen {
+        Screen { pixels: [[false; 64]; 32] }
+    }
+
+    /// Draws `rows` of 8 pixels at `x`, `y`, on a clear part of the
+    /// screen.
+    fn sprite(&mut self, x: usize, y: usize, rows: &[u8]) {
+        for (dy, row) in rows.iter().enumerate() {
+            for dx in 0..8 {
+                if row & 0x80 >> dx != 0 {
+                    self.pixels[y + dy][x + dx] = true;
+                }
+            }
+        }
+    }
+}
+
+/// Runs `name` with `preset` and compares its screen to `expected`.
+fn expect_screen(name: &str, preset: &str, expected: &Screen) {
+    let game = run(test_rom(name), preset);
+    let display = game.display();
+    let mut differences = String::new();
+    for (y, row) in expected.pixels.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            if (display.pixel(x, y) != 0) != *pixel {
+                differences.push_str(&format!(" {},{}", x, y));
+            }
+        }
+    }
+    assert!(differences.is_empty(), "{} with {} differs from the expected screen at{}", name, preset, differences);
+}
+
+/// The screen of `count` ticks, left to right and top to bottom, as
+/// drawn by the checks in `instructions.8o` and `vf.8o`.
+fn ticks(count: usize) -> Screen {
+    const TICK: [u8; 4] = [0x10, 0x20, 0xA0, 0x40];
+    let mut screen = Screen::new();
+    for index in 0..count {
+        screen.sprite(2 + index % 12 * 5, 2 + index / 12 * 6, &TICK);
+    }
+    screen
+}
+
+/// Counts the `expect` checks in the source of `name`.
+fn checks(name: &str) -> usize {
+    let source = fs::read_to_string(path("roms", &format!("{}.8o", name))).unwrap();
+    source.lines().filter(|line| line.trim_start().starts_with("expect ")).count()
+}
+
+/// The digits 0, 1, 2 and 7 of the CHIP-8 font.
+fn digit(digit: u8) -> [u8; 5] {
+    match digit {
+        0 => [0xF0, 0x90, 0x90, 0x90, 0xF0],
+        1 => [0x20, 0x60, 0x20, 0x20, 0x70],
+        2 => [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+        7 => [0xF0, 0x10, 0x20, 0x40, 0x40],
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn roms_match_sources() {
+    for rom in ROMS.iter() {
+        let source = fs::read_to_string(path("roms", &format!("{}.8o", rom.name))).unwrap();
+        let assembly = asm::assemble(&source).unwrap_or_else(|err| panic!("{}.8o:{}", rom.name, err));
+        let data = fs::read(path("roms", &format!("{}.ch8", rom.name))).unwrap();
+        assert!(assembly.rom == data, "{}.ch8 is out of date, assemble {}.8o again", rom.name, rom.name);
+    }
+}
+
+#[test]
+fn banner() {
+    check("banner");
+}
+
+#[test]
+fn instructions() {
+    check("instructions");
+    for preset in ALL_PRESETS {
+        expect_screen("instructions", preset, &ticks(checks("instructions")));
+    }
+}
+
+#[test]
+fn vf() {
+    check("vf");
+    for preset in ALL_PRESETS {
+        expect_screen("vf", preset, &ticks(checks("vf")));
+    }
+}
+
+#[test]
+fn platform() {
+    check("platform");
+    // The digits documented at the top of platform.8o.
+    let expected = [("vip", [1, 0, 1, 0, 1, 1]), ("chip48", [0, 1, 0, 1, 1, 0]),
+                    ("schip", [0, 2, 0, 1, 1, 0]), ("xochip", [0, 0, 1, 0, 0, 0])];
+    for &(preset, digits) in expected.iter() {
+        let mut screen = Screen::new();
+        for (index, &value) in digits.iter().enumerate() {
+            screen.sprite(2 + index * 7, 13, &digit(value));
+        }
+        expect_screen("platform", preset, &screen);
+    }
+}
+
+#[test]
+fn input() {
+    check("input");
+    // Key 7 was the first pressed, and 1 and A are held at the end.
+    let mut screen = Screen::new();
+    screen.sprite(28, 4, &digit(7));
+    for &key in [0x1, 0xA].iter() {
+        screen.sprite(12 + key % 8 * 5, 16 + key / 8 * 5, &[0xF0; 4]);
+    }
+    for preset in ALL_PRESETS {
+        expect_screen("input", preset, &screen);
+    }
+}
+
+#[test]
+fn hires() {
+    check("hires");
+}
@@ -0,0 +1,97 @@
+//! Drives `chip8 debug` through standard input and checks what it
+//! prints.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Calls the subroutine at 206, which sets V1 to 2, then sets V0 to 1
+/// and loops forever.
+const CALL: [u8; 10] = [0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x00, 0xEE];
+
+/// Runs the debugger on `rom` with `commands`, one per line, and
+/// returns its output. Fails if the debugger doesn't exit cleanly.
+fn debug(name: &str, rom: &[u8], commands: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.ch8", name));
+    fs::write(&path, rom).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg("debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn assert_contains(output: &str, expected: &str) {
+    assert!(output.contains(expected), "expected {:?} in:\n{}", expected, output);
+}
+
+#[test]
+fn arguments() {
+    let output = debug("arguments", &CALL, "frobnicate\nstep many\nx\nx xyz\ns 3\nregisters\nquit\n");
+
+    assert_contains(&output, "error: unknown command: frobnicate, try `help`");
+    assert_contains(&output, "error: invalid count: many");
+    assert_contains(&output, "error: missing address");
+    assert_contains(&output, "error: invalid address: xyz");
+    assert_contains(&output, "PC: 0202");
+}
+
+#[test]
+fn breakpoints() {
+    let output = debug("breakpoints", &CALL, "break 0x206\ncontinue\nd 206\nd 206\nc\n");
+
+    assert_contains(&output, "breakpoint at 206\n206  LD V1, #02");
+    assert_contains(&output, "error: no breakpoint at 206");
+    assert_contains(&output, "stuck in a loop jumping to itself\n204  JP #204");
+}
+
+#[test]
+fn next() {
+    let output = debug("next", &CALL, "next\nregisters\n");
+    assert_contains(&output, "202  LD V0, #01");
+    assert_contains(&output, "V1: 02");
+    assert_contains(&output, "stack: []");
+
+    // Stops at a breakpoint inside the call.
+    let output = debug("next-break", &CALL, "b 208\nn\n");
+    assert_contains(&output, "breakpoint at 208\n208  RET");
+}
+
+#[test]
+fn memory() {
+    let huge = usize::MAX;
+    let commands = format!(
+        "set 300 ab CD\nx 300 2\nset\nset 300\nset 300 1FF\nset FFF 1 2\nx FF0 17\nx 1 {}\nl 0 {}\n",
+        huge, huge,
+    );
+    let output = debug("memory", &CALL, &commands);
+
+    assert_contains(&output, "0300: AB CD\n");
+    assert_contains(&output, "error: usage: set ADDRESS BYTE...\n(chip8) error: usage: set ADDRESS BYTE...");
+    assert_contains(&output, "error: invalid byte: 1FF");
+    assert_contains(&output, "error: 2 bytes at 0FFF are outside of memory");
+    assert_contains(&output, "error: 17 bytes at 0FF0 are outside of memory");
+    assert_contains(&output, &format!("error: {} bytes at 0001 are outside of memory", huge));
+    assert_contains(&output, "000  F090      DB #F0, #90");
+}
+
+#[test]
+fn keys() {
+    // 6101, E19E, 00E0, 00E0 - Skips the first clear while key 1 is
+    // held down.
+    let rom = [0x61, 0x01, 0xE1, 0x9E, 0x00, 0xE0, 0x00, 0xE0];
+    let output = debug("keys", &rom, "k 10\nkeys 1 f\ns 2\nk\n");
+
+    assert_contains(&output, "error: invalid key: 10");
+    assert_contains(&output, "206  CLS");
+}